@@ -0,0 +1,154 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::path::PathBuf;
+use std::time::SystemTime;
+use tf_idf::lexer::{
+    index::{Index, IndexSettings, SearchOpts},
+    lexing::{
+        index_data, split_into_words, DocFreq, DocFreqExt, Document, Language, Links, Stopwords,
+        TfCap, Tokenizer, ENGLISH_STOPWORDS,
+    },
+};
+
+/// A paragraph of English technical prose, with the punctuation, abbreviations, links and
+/// accented words found in real documents.
+const PARAGRAPH: &str = "Rust's ownership model guarantees memory safety without a garbage \
+    collector, e.g. by checking borrows at compile time. The U.S. café in the README links to \
+    https://doc.rust-lang.org/book/ and mail@example.com for questions. Zero-cost abstractions, \
+    iterators and closures compile down to the same code you'd write by hand; the compiler \
+    inlines aggressively (see chapter 13). ";
+
+/// The same kind of text in Chinese, for the dictionary-based segmentation.
+const CHINESE_PARAGRAPH: &str = "所有权模型在没有垃圾回收器的情况下保证内存安全，\
+    借用检查在编译时完成。零成本抽象、迭代器和闭包会被编译成与手写代码相同的机器码。";
+
+/// Repeats a paragraph into a document of roughly `len` bytes.
+fn document_text(paragraph: &str, len: usize) -> String {
+    paragraph.repeat(len / paragraph.len() + 1)
+}
+
+/// Compares the throughput of the plain splitter with the tokenizer under different settings.
+fn tokenizers(c: &mut Criterion) {
+    let text = document_text(PARAGRAPH, 64 * 1024);
+    let chinese = document_text(CHINESE_PARAGRAPH, 64 * 1024);
+    let stopwords = Stopwords::new(ENGLISH_STOPWORDS.iter().map(|word| word.to_string()));
+
+    let configurations = [
+        ("default", Tokenizer::default()),
+        (
+            "fold-accents+stopwords",
+            Tokenizer {
+                fold_accents: true,
+                stopwords,
+                ..Tokenizer::default()
+            },
+        ),
+        (
+            "links-keep",
+            Tokenizer {
+                links: Links::Keep,
+                ..Tokenizer::default()
+            },
+        ),
+        (
+            "abbreviations",
+            Tokenizer {
+                abbreviations: true,
+                ..Tokenizer::default()
+            },
+        ),
+    ];
+
+    let mut group = c.benchmark_group("tokenize");
+    group.throughput(Throughput::Bytes(text.len() as u64));
+
+    group.bench_function("split_into_words", |b| {
+        b.iter(|| split_into_words(black_box(&text)))
+    });
+    for (name, tokenizer) in &configurations {
+        group.bench_with_input(BenchmarkId::new("tokenizer", name), &text, |b, text| {
+            b.iter(|| tokenizer.tokenize(black_box(text)))
+        });
+    }
+
+    let chinese_tokenizer = Tokenizer {
+        language: Language::Chinese,
+        ..Tokenizer::default()
+    };
+    // The dictionary is loaded on first use, which shouldn't be measured
+    chinese_tokenizer.tokenize(CHINESE_PARAGRAPH);
+    group.throughput(Throughput::Bytes(chinese.len() as u64));
+    group.bench_with_input(
+        BenchmarkId::new("tokenizer", "chinese"),
+        &chinese,
+        |b, text| b.iter(|| chinese_tokenizer.tokenize(black_box(text))),
+    );
+
+    group.finish();
+}
+
+/// Builds an index of `count` documents of about 16 KiB each.
+fn documents(count: usize) -> Vec<Document> {
+    let tokenizer = Tokenizer::default();
+
+    (0..count)
+        .map(|n| {
+            let path = PathBuf::from(format!("doc{}.txt", n));
+            // Every document gets a few terms of its own, so the vocabulary grows with the corpus
+            let text = format!(
+                "{} unique{} term{}",
+                document_text(PARAGRAPH, 16 * 1024),
+                n,
+                n
+            );
+            let tokens = tokenizer.tokenize(&text);
+            let len = tokens.len();
+
+            Document {
+                data: DocFreq::single(path.clone(), index_data(tokens, TfCap::None)),
+                path,
+                last_modified: SystemTime::now(),
+                modified: None,
+                tokens: None,
+                title: None,
+                title_data: Default::default(),
+                pages: None,
+                len,
+                fields: Default::default(),
+                raw_words: None,
+            }
+        })
+        .collect()
+}
+
+/// Measures counting term frequencies, building an index and searching it.
+fn indexing(c: &mut Criterion) {
+    let text = document_text(PARAGRAPH, 64 * 1024);
+    let tokens = Tokenizer::default().tokenize(&text);
+
+    let mut group = c.benchmark_group("index");
+    group.throughput(Throughput::Bytes(text.len() as u64));
+    group.bench_function("index_data", |b| {
+        b.iter(|| index_data(black_box(tokens.clone()), TfCap::None))
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group("corpus");
+    for count in [10, 100] {
+        let docs = documents(count);
+        group.throughput(Throughput::Elements(count as u64));
+        group.bench_with_input(BenchmarkId::new("Index::new", count), &docs, |b, docs| {
+            b.iter(|| Index::new(IndexSettings::default(), black_box(docs.clone())))
+        });
+
+        let index = Index::new(IndexSettings::default(), docs);
+        group.bench_with_input(
+            BenchmarkId::new("Index::search", count),
+            &index,
+            |b, index| b.iter(|| index.search(black_box("memory safety"), &SearchOpts::default())),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, tokenizers, indexing);
+criterion_main!(benches);
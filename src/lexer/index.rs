@@ -0,0 +1,1649 @@
+use super::lexing::{
+    fold_accents, raw_words, Document, Idf, ScorerComparison, TermScore, TfCap, Tokenizer,
+};
+use super::scoring::{
+    document_frequencies, document_terms, exact_factor, minhash, minhash_similarity,
+    proximity_factor, recency_factor, Bm25Scorer, IdfVariant, QueryNorm, Scorer, TfIdfScorer,
+    TfScorer,
+};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::time::SystemTime;
+
+/// The default multiplier applied to matches in a document's title.
+pub const DEFAULT_TITLE_BOOST: f32 = 2.0;
+
+/// The version of the index format, increased whenever the way documents are indexed changes.
+pub const INDEX_VERSION: u32 = 4;
+
+/// Represents the settings an index was built with.
+///
+/// These are saved along with the documents, so an index built with different settings than the
+/// current ones can be detected and rebuilt.
+///
+/// # Fields
+///
+/// * `version` - The `INDEX_VERSION` the index was built with. Indexes from before versioning
+///   have version 0.
+/// * `tokenizer` - The `Tokenizer` the documents were tokenized with.
+/// * `tf_cap` - The `TfCap` the term frequencies were calculated with.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexSettings {
+    #[serde(default)]
+    pub version: u32,
+    pub tokenizer: Tokenizer,
+    #[serde(default)]
+    pub tf_cap: TfCap,
+}
+
+impl IndexSettings {
+    /// Lists the settings that differ between these settings and `other`.
+    ///
+    /// Documents tokenized with one set of settings can't be searched with another, since the
+    /// query terms wouldn't be tokenized the same way as the documents, so this names the options
+    /// responsible when an index is incompatible with the current ones.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The settings to compare with.
+    ///
+    /// # Returns
+    ///
+    /// The names of the differing settings, by the command line option that controls them, or an
+    /// empty vector if the settings are the same.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tf_idf::lexer::index::IndexSettings;
+    ///
+    /// let mut other = IndexSettings::default();
+    /// other.tokenizer.fold_accents = true;
+    ///
+    /// assert_eq!(IndexSettings::default().differences(&other), vec!["--fold-accents"]);
+    /// assert!(IndexSettings::default().differences(&IndexSettings::default()).is_empty());
+    /// ```
+    pub fn differences(&self, other: &IndexSettings) -> Vec<&'static str> {
+        let (ours, theirs) = (&self.tokenizer, &other.tokenizer);
+        [
+            ("index version", self.version != other.version),
+            ("--fold-accents", ours.fold_accents != theirs.fold_accents),
+            ("--stopwords", ours.stopwords != theirs.stopwords),
+            ("--apostrophes", ours.apostrophes != theirs.apostrophes),
+            ("--links", ours.links != theirs.links),
+            ("--language", ours.language != theirs.language),
+            (
+                "--abbreviations",
+                ours.abbreviations != theirs.abbreviations,
+            ),
+            ("--keep-decimals", ours.decimals != theirs.decimals),
+            ("--pipeline", ours.pipeline != theirs.pipeline),
+            (
+                "--max-token-len",
+                ours.max_token_len != theirs.max_token_len,
+            ),
+            ("--tf-cap", self.tf_cap != other.tf_cap),
+        ]
+        .into_iter()
+        .filter(|(_, differs)| *differs)
+        .map(|(name, _)| name)
+        .collect()
+    }
+}
+
+impl Default for IndexSettings {
+    fn default() -> Self {
+        IndexSettings {
+            version: INDEX_VERSION,
+            tokenizer: Tokenizer::default(),
+            tf_cap: TfCap::default(),
+        }
+    }
+}
+
+/// Represents how a term that occurs more than once in a query is scored.
+///
+/// # Variants
+///
+/// * `Dedupe` - Repeated terms are scored once, so accidental repetition doesn't skew the
+///   results. This is the default.
+/// * `Boost` - Every occurrence is scored, so repeating a term acts as a boost of that term.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RepeatedTerms {
+    #[default]
+    Dedupe,
+    Boost,
+}
+
+impl FromStr for RepeatedTerms {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dedupe" => Ok(RepeatedTerms::Dedupe),
+            "boost" => Ok(RepeatedTerms::Boost),
+            _ => Err(format!("Unknown repeated terms policy: {}", s)),
+        }
+    }
+}
+
+/// Represents how the terms of a multi-term query are combined.
+///
+/// # Variants
+///
+/// * `Or` - A document matches if it contains any of the terms. This is the default.
+/// * `And` - A document only matches if it contains all of the terms, in its body or title.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Operator {
+    #[default]
+    Or,
+    And,
+}
+
+impl FromStr for Operator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "or" => Ok(Operator::Or),
+            "and" => Ok(Operator::And),
+            _ => Err(format!("Unknown operator: {}", s)),
+        }
+    }
+}
+
+/// Represents the order of the search results.
+///
+/// # Variants
+///
+/// * `Desc` - The most relevant result comes first. This is the default.
+/// * `Asc` - The least relevant result comes first, to explore the long tail of a corpus.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Order {
+    #[default]
+    Desc,
+    Asc,
+}
+
+impl FromStr for Order {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "desc" => Ok(Order::Desc),
+            "asc" => Ok(Order::Asc),
+            _ => Err(format!("Unknown order: {}", s)),
+        }
+    }
+}
+
+/// Represents how search results with the same score are ordered.
+///
+/// On a uniform corpus a common term can give many documents the same score, which would leave
+/// their order to the order of the documents in the index.
+///
+/// # Variants
+///
+/// * `None` - Equal scores keep the order of the documents. This is the default.
+/// * `Len` - Shorter documents, with fewer distinct terms, come first, since the match is a larger
+///   part of them.
+/// * `Recency` - More recently modified documents come first. Documents without a known
+///   modification time come last.
+/// * `Path` - Documents are ordered alphabetically by path.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Tiebreak {
+    #[default]
+    None,
+    Len,
+    Recency,
+    Path,
+}
+
+impl Tiebreak {
+    /// Compares two documents with the same score, the one that should rank first being less.
+    pub fn compare(&self, a: &Document, b: &Document) -> Ordering {
+        match self {
+            Tiebreak::None => Ordering::Equal,
+            Tiebreak::Len => {
+                let len = |doc: &Document| doc.term_freq().map_or(0, |term_freq| term_freq.len());
+                len(a).cmp(&len(b))
+            }
+            // Documents without a modification time compare greater, so they come last
+            Tiebreak::Recency => match (a.modified, b.modified) {
+                (Some(a), Some(b)) => b.cmp(&a),
+                (a, b) => a.is_none().cmp(&b.is_none()),
+            },
+            Tiebreak::Path => a.path.cmp(&b.path),
+        }
+    }
+}
+
+impl FromStr for Tiebreak {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Tiebreak::None),
+            "len" => Ok(Tiebreak::Len),
+            "recency" => Ok(Tiebreak::Recency),
+            "path" => Ok(Tiebreak::Path),
+            _ => Err(format!("Unknown tiebreak: {}", s)),
+        }
+    }
+}
+
+/// Represents the options of a search that don't affect the index.
+///
+/// # Fields
+///
+/// * `title_boost` - The multiplier applied to the score of matches in a document's title.
+/// * `proximity_boost` - How much documents where the query terms appear close together are
+///   boosted. Zero disables the boost.
+/// * `recency_boost` - The half-life in days of the decay applied to the score of older
+///   documents. Zero disables the boost.
+/// * `exact_boost` - How much documents containing the query words exactly as typed, before
+///   normalization, are boosted. Zero disables the boost.
+/// * `query_norm` - How the summed score of a multi-term query is normalized.
+/// * `idf` - The formula used to calculate the inverse document frequency.
+/// * `show_zero_scores` - Whether documents that don't match the query are included too.
+/// * `repeated_terms` - How a term that occurs more than once in the query is scored.
+/// * `operator` - How the terms of a multi-term query are combined.
+/// * `order` - The order of the results of `Index::search` and its variants.
+/// * `tiebreak` - How results with the same score are ordered. Results that are still tied keep
+///   the order of the documents in the index.
+/// * `auto_stopwords` - The largest fraction of the documents a query term may occur in. More
+///   common query terms are dropped, see `Index::auto_stopwords`. One disables the dropping.
+/// * `since` - The modification time before which documents are left out of the results, if
+///   set. Documents without a known modification time are left out too.
+/// * `compare_scorers` - Whether every result also holds its score under each of the built-in
+///   ranking formulas, see `ScorerComparison`.
+/// * `substring` - Whether query terms starting with `*` match the terms containing or ending with
+///   the rest of them, see `Index::expand`. Terms ending with `*` always match as prefix.
+/// * `boosts` - The multipliers applied to the score of individual documents by their path, if
+///   any. Documents that aren't listed keep their score.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SearchOpts<'a> {
+    pub title_boost: f32,
+    pub proximity_boost: f32,
+    pub recency_boost: f32,
+    pub exact_boost: f32,
+    pub query_norm: QueryNorm,
+    pub idf: IdfVariant,
+    pub show_zero_scores: bool,
+    pub repeated_terms: RepeatedTerms,
+    pub operator: Operator,
+    pub order: Order,
+    pub tiebreak: Tiebreak,
+    pub auto_stopwords: f32,
+    pub since: Option<SystemTime>,
+    pub compare_scorers: bool,
+    pub substring: bool,
+    pub boosts: Option<&'a HashMap<PathBuf, f32>>,
+}
+
+impl Default for SearchOpts<'_> {
+    fn default() -> Self {
+        SearchOpts {
+            title_boost: DEFAULT_TITLE_BOOST,
+            proximity_boost: 0.0,
+            recency_boost: 0.0,
+            exact_boost: 0.0,
+            query_norm: QueryNorm::default(),
+            idf: IdfVariant::default(),
+            show_zero_scores: false,
+            repeated_terms: RepeatedTerms::default(),
+            operator: Operator::default(),
+            order: Order::default(),
+            tiebreak: Tiebreak::default(),
+            auto_stopwords: 1.0,
+            since: None,
+            compare_scorers: false,
+            substring: false,
+            boosts: None,
+        }
+    }
+}
+
+/// Represents the tf-idf vector of the body of a document.
+///
+/// # Fields
+///
+/// * `weights` - The tf-idf weight of every term of the body with a nonzero weight.
+/// * `norm` - The Euclidean norm of the weights, zero for a document without weighted terms.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TfIdfVector {
+    pub weights: HashMap<String, f32>,
+    pub norm: f32,
+}
+
+/// Represents a loaded index, the documents together with the settings they were indexed with.
+///
+/// The document frequency of every term is calculated once when the index is created, so it
+/// isn't recalculated for every search. Documents added or removed with `add` and `remove` update
+/// the document frequencies incrementally, so the `documents` shouldn't be modified directly. Searching only borrows the index, and the index is `Send`
+/// and `Sync`, so a loaded index can be shared between threads, for example in an `Arc`, and
+/// searched concurrently.
+///
+/// The tf-idf vectors of the documents, which `similar` and `document_similarities` compare, are
+/// built lazily the first time they are needed and kept for later calls, see `vectors`.
+///
+/// Only the settings, the documents and whether the index is partial are serialized. The document
+/// frequencies are calculated again when an index is deserialized, and the vectors when they are
+/// first needed.
+///
+/// # Fields
+///
+/// * `settings` - The `IndexSettings` the index was built with.
+/// * `documents` - The indexed documents.
+/// * `partial` - Whether the index is a checkpoint saved while indexing, which doesn't hold all
+///   the documents yet.
+///
+/// # Examples
+///
+/// ```
+/// use tf_idf::lexer::index::{Index, IndexSettings, SearchOpts};
+///
+/// let index = Index::new(IndexSettings::default(), Vec::new());
+///
+/// let json = serde_json::to_string(&index).unwrap();
+/// let results = index.search("rust", &SearchOpts::default());
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "IndexData")]
+pub struct Index {
+    pub settings: IndexSettings,
+    pub documents: Vec<Document>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub partial: bool,
+    #[serde(skip)]
+    df: BTreeMap<String, usize>,
+    #[serde(skip)]
+    vectors: OnceLock<(IdfVariant, Vec<TfIdfVector>)>,
+}
+
+/// The serialized fields of an `Index`, from which the document frequencies are calculated.
+#[derive(Deserialize)]
+struct IndexData {
+    settings: IndexSettings,
+    documents: Vec<Document>,
+    #[serde(default)]
+    partial: bool,
+}
+
+impl From<IndexData> for Index {
+    fn from(data: IndexData) -> Self {
+        let mut index = Index::new(data.settings, data.documents);
+        index.partial = data.partial;
+        index
+    }
+}
+
+impl Index {
+    /// Creates a complete index of the documents, calculating the document frequency of every
+    /// term.
+    pub fn new(settings: IndexSettings, documents: Vec<Document>) -> Index {
+        // Sorted, so the vocabulary can be searched by prefix
+        let df = document_frequencies(&documents).into_iter().collect();
+
+        Index {
+            settings,
+            documents,
+            partial: false,
+            df,
+            vectors: OnceLock::new(),
+        }
+    }
+
+    /// Adds a document to the index, replacing the document with the same path if there is one.
+    ///
+    /// The document frequencies are updated incrementally: the count of every distinct term of
+    /// the document is incremented, after decrementing those of the replaced document. This
+    /// takes time in the size of the document rather than of the whole index.
+    ///
+    /// # Returns
+    ///
+    /// The replaced document, if any.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let mut index = load_index(Path::new("books/.data.json"))?;
+    ///
+    /// index.add(document);
+    /// serialize_and_save(&index, Path::new("books/.data.json"))?;
+    /// ```
+    pub fn add(&mut self, document: Document) -> Option<Document> {
+        let replaced = self.remove(&document.path.clone());
+        self.vectors = OnceLock::new();
+
+        for term in document_terms(&document) {
+            *self.df.entry(term.clone()).or_insert(0) += 1;
+        }
+        self.documents.push(document);
+
+        replaced
+    }
+
+    /// Removes the document at `path` from the index.
+    ///
+    /// The document frequency of every distinct term of the document is decremented, and terms
+    /// that no longer occur in any document are dropped, like in `add`.
+    ///
+    /// # Returns
+    ///
+    /// The removed document, or `None` if no document has that path.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let mut index = load_index(Path::new("books/.data.json"))?;
+    ///
+    /// if index.remove(Path::new("books/old.pdf")).is_some() {
+    ///     serialize_and_save(&index, Path::new("books/.data.json"))?;
+    /// }
+    /// ```
+    pub fn remove(&mut self, path: &Path) -> Option<Document> {
+        let position = self.documents.iter().position(|doc| doc.path == path)?;
+        let document = self.documents.remove(position);
+        self.vectors = OnceLock::new();
+
+        for term in document_terms(&document) {
+            if let Some(count) = self.df.get_mut(term) {
+                *count -= 1;
+                if *count == 0 {
+                    self.df.remove(term);
+                }
+            }
+        }
+
+        Some(document)
+    }
+
+    /// Returns the tf-idf vectors of the bodies of the documents.
+    ///
+    /// The vectors are built on the first call and cached in the index, so ranking documents by
+    /// their vectors again, as `similar` does for every document it compares, doesn't weight every
+    /// term of every document again. Only the vectors of the first IDF formula asked for are
+    /// cached, since a session uses a single one, and those of another formula are built for the
+    /// call. Adding or removing a document drops the cache, since the document frequencies change.
+    ///
+    /// # Arguments
+    ///
+    /// * `idf` - The formula used to calculate the inverse document frequency.
+    ///
+    /// # Returns
+    ///
+    /// The vector of every document, in the order of `documents`.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let index = load_index(Path::new("books/.data.json"))?;
+    ///
+    /// for (doc, vector) in index.documents.iter().zip(index.vectors(IdfVariant::Smooth).iter()) {
+    ///     println!("{:?}: {} terms", doc.path, vector.weights.len());
+    /// }
+    /// ```
+    pub fn vectors(&self, idf: IdfVariant) -> Cow<'_, [TfIdfVector]> {
+        let (cached_idf, vectors) = self.vectors.get_or_init(|| (idf, self.build_vectors(idf)));
+        if *cached_idf == idf {
+            Cow::Borrowed(vectors)
+        } else {
+            Cow::Owned(self.build_vectors(idf))
+        }
+    }
+
+    /// Weights the terms of the body of every document by their tf-idf. See `vectors`.
+    fn build_vectors(&self, idf: IdfVariant) -> Vec<TfIdfVector> {
+        let n_docs = self.documents.len();
+
+        self.documents
+            .iter()
+            .map(|doc| {
+                let weights: HashMap<String, f32> = doc
+                    .term_freq()
+                    .into_iter()
+                    .flatten()
+                    .map(|(term, tf)| {
+                        (
+                            term.clone(),
+                            tf * idf.idf(n_docs, self.document_frequency(term)),
+                        )
+                    })
+                    .filter(|(_, weight)| *weight != 0.0)
+                    .collect();
+                let norm = weights
+                    .values()
+                    .map(|weight| weight.powi(2))
+                    .sum::<f32>()
+                    .sqrt();
+                TfIdfVector { weights, norm }
+            })
+            .collect()
+    }
+
+    /// Returns the number of documents `term` occurs in, either in the body, the title or another
+    /// field.
+    pub fn document_frequency(&self, term: &str) -> usize {
+        self.df.get(term).copied().unwrap_or(0)
+    }
+
+    /// Finds the query terms that are too common in the index to be useful, acting as stop words
+    /// determined by the corpus.
+    ///
+    /// A term is too common if the fraction of the documents it occurs in is larger than
+    /// `max_df`. Terms are only dropped if at least one term of the query remains, so a query of
+    /// only common terms still finds something.
+    ///
+    /// # Arguments
+    ///
+    /// * `terms` - The tokenized query terms.
+    /// * `max_df` - The largest fraction of the documents a term may occur in, between 0 and 1.
+    ///
+    /// # Returns
+    ///
+    /// The terms to drop from the query, in query order, or an empty vector if none should be
+    /// dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let index = load_index(Path::new("books/.data.json"))?;
+    /// let terms = index.settings.tokenizer.tokenize("the rust book");
+    ///
+    /// println!("Dropping {:?}", index.auto_stopwords(&terms, 0.5));
+    /// ```
+    pub fn auto_stopwords(&self, terms: &[String], max_df: f32) -> Vec<String> {
+        let n_docs = self.documents.len();
+        let common: Vec<String> = terms
+            .iter()
+            .filter(|term| self.document_frequency(term) as f32 > max_df * n_docs as f32)
+            .cloned()
+            .collect();
+
+        if terms.iter().all(|term| common.contains(term)) {
+            Vec::new()
+        } else {
+            common
+        }
+    }
+
+    /// Parses a query into its terms, scoping the terms written as `field:term` to that field.
+    ///
+    /// See `parse_query`.
+    pub fn parse_query(&self, query: &str) -> Vec<QueryTerm> {
+        parse_query(query, &self.settings.tokenizer, &self.documents)
+    }
+
+    /// Expands a wildcard query term to the terms of the index it matches.
+    ///
+    /// A term ending with `*`, such as `comput*`, matches the terms starting with the rest of it,
+    /// found with a range query on the sorted vocabulary. With `substring`, a term starting with
+    /// `*` also matches: `*work*` the terms containing "work", such as "network" and "framework",
+    /// and `*work` the terms ending with it. These are found by scanning the whole vocabulary, so
+    /// they are much slower on a large index.
+    ///
+    /// # Arguments
+    ///
+    /// * `term` - The query term, as tokenized.
+    /// * `substring` - Whether terms starting with `*` are expanded too.
+    ///
+    /// # Returns
+    ///
+    /// `None` if the term isn't a wildcard that is expanded, otherwise the matching terms of the
+    /// index in alphabetical order, which may be none.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let index = load_index(Path::new("books/.data.json"))?;
+    ///
+    /// println!("{:?}", index.expand("comput*", false)); // Some(["compute", "computer"])
+    /// println!("{:?}", index.expand("*work*", true)); // Some(["framework", "network", "work"])
+    /// assert_eq!(index.expand("*work*", false), None);
+    /// ```
+    pub fn expand(&self, term: &str, substring: bool) -> Option<Vec<String>> {
+        let (leading, rest) = match term.strip_prefix('*') {
+            Some(rest) => (true, rest),
+            None => (false, term),
+        };
+        let (trailing, part) = match rest.strip_suffix('*') {
+            Some(part) => (true, part),
+            None => (false, rest),
+        };
+        if part.is_empty() || part.contains('*') {
+            return None;
+        }
+
+        let terms = match (leading, trailing) {
+            (false, true) => self
+                .df
+                .range(part.to_string()..)
+                .take_while(|(term, _)| term.starts_with(part))
+                .map(|(term, _)| term.clone())
+                .collect(),
+            (true, true) if substring => self
+                .df
+                .keys()
+                .filter(|term| term.contains(part))
+                .cloned()
+                .collect(),
+            (true, false) if substring => self
+                .df
+                .keys()
+                .filter(|term| term.ends_with(part))
+                .cloned()
+                .collect(),
+            _ => return None,
+        };
+
+        Some(terms)
+    }
+
+    /// Suggests the terms of the index that complete a partial term, for autocompletion.
+    ///
+    /// The prefix is lowercased, and its accents are folded if the index was built with
+    /// `fold_accents`, like the indexed terms. The completing terms are found with a range query
+    /// on the sorted vocabulary, and ranked by their total frequency in the corpus: how often they
+    /// occur in the bodies of all the documents, estimated from the term frequencies and the
+    /// document lengths. Terms with the same total frequency are ranked by the number of
+    /// documents they occur in, then alphabetically.
+    ///
+    /// # Arguments
+    ///
+    /// * `prefix` - The partial term.
+    /// * `n` - The largest number of suggestions to return.
+    ///
+    /// # Returns
+    ///
+    /// Up to `n` terms starting with `prefix`, most frequent first. The prefix itself is included
+    /// if it is a term of the index.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let index = load_index(Path::new("books/.data.json"))?;
+    ///
+    /// println!("{:?}", index.suggest("mem", 5)); // ["memory", "memoization", "member"]
+    /// ```
+    pub fn suggest(&self, prefix: &str, n: usize) -> Vec<String> {
+        let prefix = prefix.to_lowercase();
+        let prefix = if self.settings.tokenizer.fold_accents {
+            fold_accents(&prefix)
+        } else {
+            prefix
+        };
+
+        let mut candidates: Vec<(&String, f32, usize)> = self
+            .df
+            .range(prefix.clone()..)
+            .take_while(|(term, _)| term.starts_with(&prefix))
+            .map(|(term, df)| {
+                let total: f32 = self
+                    .documents
+                    .iter()
+                    .filter_map(|doc| Some(doc.term_freq()?.get(term)? * doc.len as f32))
+                    .sum();
+                (term, total, *df)
+            })
+            .collect();
+        candidates.sort_by(|a, b| {
+            b.1.total_cmp(&a.1)
+                .then_with(|| b.2.cmp(&a.2))
+                .then_with(|| a.0.cmp(b.0))
+        });
+
+        candidates
+            .into_iter()
+            .take(n)
+            .map(|(term, _, _)| term.clone())
+            .collect()
+    }
+
+    /// Returns the number of distinct terms in the index.
+    pub fn term_count(&self) -> usize {
+        self.df.len()
+    }
+
+    /// Searches the index for the given query and returns the ranked results.
+    ///
+    /// The query is tokenized with the `Tokenizer` of the index settings, the same way as the
+    /// documents. Words written as `field:term`, like `author:smith`, only match in that field of
+    /// the documents, see `parse_query`. Repeated query terms are scored once, unless `opts.repeated_terms` lets them
+    /// act as a boost. For every document and query term the term frequency is multiplied by the
+    /// inverse document frequency (IDF), calculated with the formula selected by `opts.idf`. The
+    /// scores of all query terms are summed and then normalized according to `opts.query_norm`.
+    /// `search_with` ranks with another formula instead.
+    ///
+    /// Query terms occurring in a larger fraction of the documents than `opts.auto_stopwords`
+    /// are dropped, see `auto_stopwords`.
+    ///
+    /// With `Operator::And` as `opts.operator`, documents that don't contain every query term
+    /// score zero.
+    ///
+    /// The term frequency of matches in the title of a document is multiplied by the title boost
+    /// and added to the term frequency in the body.
+    ///
+    /// Wildcard query terms, such as `comput*`, are expanded with `expand`, and score as the sum
+    /// of the scores of the terms they match. A wildcard term counts as present for
+    /// `Operator::And` if any of its terms is.
+    ///
+    /// When `opts.proximity_boost` is set, the score of documents where the query terms appear
+    /// close together is multiplied by the `proximity_factor`. This needs the positions of the
+    /// terms, so it only applies to documents indexed with their tokens.
+    ///
+    /// When `opts.exact_boost` is set, the score is multiplied by the `exact_factor` of the words
+    /// of the query as typed, so documents containing "Rust" rank above those only containing
+    /// "rust" for the query "Rust". This needs the raw words of the documents, so it only applies
+    /// to documents indexed with them. Query words that are stop words, including the ones dropped
+    /// by `opts.auto_stopwords`, wildcards or scoped to a field don't count.
+    ///
+    /// When `opts.boosts` is set, the score of every listed document is multiplied by its boost.
+    ///
+    /// When `opts.recency_boost` is set, the score is multiplied by the `recency_factor` of the
+    /// modification time of the document, so of two equally relevant documents the newer ranks
+    /// first. Documents without a known modification time aren't affected.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - A string representing the query to search for.
+    /// * `opts` - The `SearchOpts` holding the IDF variant, the boosts and the query
+    ///   normalization.
+    ///
+    /// # Returns
+    ///
+    /// A vector of `Idf` structs holding the path and score of every matching document, sorted by
+    /// descending score, or ascending if `opts.order` says so. Equal scores are ordered by
+    /// `opts.tiebreak`, whatever the order of the scores. Documents with a zero score are left
+    /// out unless `opts.show_zero_scores` is set. Every `Idf` also holds the per-term breakdown
+    /// of its score, and the pages the query terms occur on if the pages of the document were
+    /// stored.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let index = load_index(Path::new("books/.data.json"))?;
+    ///
+    /// for result in index.search("memory safety", &SearchOpts::default()) {
+    ///     println!("{:?}: {}", result.path, result.tf);
+    /// }
+    /// ```
+    pub fn search(&self, query: &str, opts: &SearchOpts) -> Vec<Idf> {
+        self.search_with(query, opts, &TfIdfScorer { idf: opts.idf })
+    }
+
+    /// Searches the index like `search`, but ranks the documents with the given `Scorer`.
+    ///
+    /// For every document and query term, `scorer` scores the term frequency in the body plus
+    /// the boosted term frequency in the title. Everything else is the same as in `search`.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let index = load_index(Path::new("books/.data.json"))?;
+    ///
+    /// let results = index.search_with("memory safety", &SearchOpts::default(), &Bm25Scorer::default());
+    /// ```
+    pub fn search_with(&self, query: &str, opts: &SearchOpts, scorer: &dyn Scorer) -> Vec<Idf> {
+        let mut ranked = self.ranked(query, opts, scorer);
+        // The best ranked result is the greatest
+        ranked.sort_unstable_by(|a, b| b.cmp(a));
+
+        ranked.into_iter().map(|ranked| ranked.idf).collect()
+    }
+
+    /// Scores every document for the query with `scorer` like `score`, paired with what it is
+    /// ranked by.
+    fn ranked(&self, query: &str, opts: &SearchOpts, scorer: &dyn Scorer) -> Vec<Ranked<'_>> {
+        let documents: HashMap<&Path, &Document> = self
+            .documents
+            .iter()
+            .map(|doc| (doc.path.as_path(), doc))
+            .collect();
+
+        self.score(query, opts, scorer)
+            .into_iter()
+            .enumerate()
+            .map(|(position, idf)| Ranked {
+                doc: documents.get(idf.path.as_path()).copied(),
+                idf,
+                position,
+                order: opts.order,
+                tiebreak: opts.tiebreak,
+            })
+            .collect()
+    }
+
+    /// Counts the documents that match the query, with a score above zero.
+    ///
+    /// The documents are scored like in `search`, but the results aren't sorted or collected, so
+    /// this is a cheap way to find out how common the terms of a query are in the corpus.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let index = load_index(Path::new("books/.data.json"))?;
+    ///
+    /// println!("{} documents mention rust", index.count("rust", &SearchOpts::default()));
+    /// ```
+    pub fn count(&self, query: &str, opts: &SearchOpts) -> usize {
+        self.count_with(query, opts, &TfIdfScorer { idf: opts.idf })
+    }
+
+    /// Counts the documents that match the query like `count`, but scores them with the given
+    /// `Scorer` like `search_with`.
+    pub fn count_with(&self, query: &str, opts: &SearchOpts, scorer: &dyn Scorer) -> usize {
+        self.score(query, opts, scorer)
+            .iter()
+            .filter(|idf| idf.tf > 0.0)
+            .count()
+    }
+
+    /// Scores every document for the query with `scorer`, in the order of the documents. See
+    /// `search`.
+    fn score(&self, query: &str, opts: &SearchOpts, scorer: &dyn Scorer) -> Vec<Idf> {
+        let mut idf_buff: Vec<Idf> = Vec::new();
+        let mut query_terms = self.parse_query(query);
+        if opts.repeated_terms == RepeatedTerms::Dedupe {
+            let mut seen: HashSet<QueryTerm> = HashSet::new();
+            query_terms.retain(|term| seen.insert(term.clone()));
+        }
+        let words: Vec<String> = query_terms.iter().map(|term| term.term.clone()).collect();
+        let common = self.auto_stopwords(&words, opts.auto_stopwords);
+        query_terms.retain(|term| !common.contains(&term.term));
+        let terms: Vec<String> = query_terms.iter().map(|term| term.term.clone()).collect();
+        // Only the words of the query that yield unscoped, non-wildcard terms that are kept
+        let exact_words: Vec<String> = if opts.exact_boost > 0.0 {
+            let exact_terms: HashSet<&str> = query_terms
+                .iter()
+                .filter(|term| term.field.is_none() && !term.term.contains('*'))
+                .map(|term| term.term.as_str())
+                .collect();
+            raw_words(query)
+                .into_iter()
+                .filter(|word| {
+                    let tokens = self.settings.tokenizer.tokenize(word);
+                    !tokens.is_empty()
+                        && tokens
+                            .iter()
+                            .all(|token| exact_terms.contains(token.as_str()))
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+        // Wildcard terms are expanded once, and score as the sum of the terms they match
+        let expansions: Vec<(Vec<String>, bool)> = query_terms
+            .iter()
+            .map(|term| match self.expand(&term.term, opts.substring) {
+                Some(expansion) => (expansion, true),
+                None => (vec![term.term.clone()], false),
+            })
+            .collect();
+        let matched_terms: Vec<String> = expansions
+            .iter()
+            .flat_map(|(expansion, _)| expansion.iter().cloned())
+            .collect();
+        let now = SystemTime::now();
+        let n_docs = self.documents.len();
+        let avg_len =
+            self.documents.iter().map(|doc| doc.len).sum::<usize>() as f32 / n_docs.max(1) as f32;
+
+        for doc in &self.documents {
+            if let Some(since) = opts.since {
+                if doc.modified.is_none_or(|modified| modified < since) {
+                    continue;
+                }
+            }
+
+            let mut score = 0.0;
+            let mut matches_all = true;
+            let mut explanation: Vec<TermScore> = Vec::new();
+            let mut comparison = ScorerComparison::default();
+
+            for (query_term, (expansion, wildcard)) in query_terms.iter().zip(&expansions) {
+                let mut matched = false;
+                for term in expansion {
+                    let body_tf = doc
+                        .term_freq()
+                        .and_then(|term_freq| term_freq.get(term))
+                        .cloned()
+                        .unwrap_or(0.0);
+                    let title_tf = doc.title_data.get(term).cloned().unwrap_or(0.0);
+                    let (tf, title_tf) = match query_term.field.as_deref() {
+                        None => (body_tf, title_tf),
+                        Some("body") => (body_tf, 0.0),
+                        Some("title") => (0.0, title_tf),
+                        Some(field) => (
+                            doc.fields
+                                .get(field)
+                                .and_then(|term_freq| term_freq.get(term))
+                                .cloned()
+                                .unwrap_or(0.0),
+                            0.0,
+                        ),
+                    };
+                    let df = self.document_frequency(term);
+                    let boosted_tf = tf + opts.title_boost * title_tf;
+                    let (idf, contribution) = match df {
+                        0 => (0.0, 0.0),
+                        df => (
+                            opts.idf.idf(n_docs, df),
+                            scorer.score(boosted_tf, df, n_docs, doc.len, avg_len),
+                        ),
+                    };
+                    matched |= tf > 0.0 || title_tf > 0.0;
+                    // A wildcard matches many terms, of which only the ones in the document are shown
+                    if *wildcard && tf == 0.0 && title_tf == 0.0 {
+                        continue;
+                    }
+                    if opts.compare_scorers && df > 0 {
+                        let compare = |scorer: &dyn Scorer| {
+                            scorer.score(boosted_tf, df, n_docs, doc.len, avg_len)
+                        };
+                        comparison.tf += compare(&TfScorer);
+                        comparison.tf_idf += compare(&TfIdfScorer { idf: opts.idf });
+                        comparison.bm25 += compare(&Bm25Scorer::default());
+                    }
+
+                    score += contribution;
+                    explanation.push(TermScore {
+                        term: QueryTerm {
+                            field: query_term.field.clone(),
+                            term: term.clone(),
+                        }
+                        .to_string(),
+                        tf,
+                        title_tf,
+                        df,
+                        idf,
+                        contribution,
+                    });
+                }
+                matches_all &= matched;
+            }
+
+            // The boosts multiply the score, so the compared scores get the same factor
+            let mut factor = 1.0;
+            if opts.operator == Operator::And && !matches_all {
+                factor = 0.0;
+            }
+
+            if opts.proximity_boost > 0.0 {
+                if let Some(tokens) = &doc.tokens {
+                    factor *= proximity_factor(tokens, &terms, opts.proximity_boost);
+                }
+            }
+
+            if opts.exact_boost > 0.0 {
+                if let Some(raw_words) = &doc.raw_words {
+                    factor *= exact_factor(raw_words, &exact_words, opts.exact_boost);
+                }
+            }
+
+            if opts.recency_boost > 0.0 {
+                if let Some(modified) = doc.modified {
+                    factor *= recency_factor(modified, now, opts.recency_boost);
+                }
+            }
+            if let Some(boost) = opts.boosts.and_then(|boosts| boosts.get(&doc.path)) {
+                factor *= boost;
+            }
+            score *= factor;
+
+            // Documents that don't contain any query term would only pad the results with zeros
+            if score <= 0.0 && !opts.show_zero_scores {
+                continue;
+            }
+
+            let idf = Idf {
+                path: doc.path.clone(),
+                tf: opts.query_norm.apply(score, &terms),
+                explanation,
+                pages: doc.pages_of(&matched_terms),
+                comparison: opts.compare_scorers.then(|| ScorerComparison {
+                    tf: opts.query_norm.apply(comparison.tf * factor, &terms),
+                    tf_idf: opts.query_norm.apply(comparison.tf_idf * factor, &terms),
+                    bm25: opts.query_norm.apply(comparison.bm25 * factor, &terms),
+                }),
+            };
+
+            if !idf_buff.contains(&idf) {
+                idf_buff.push(idf);
+            }
+        }
+
+        idf_buff
+    }
+
+    /// Collapses near-duplicate documents in ranked results, keeping the best ranked document of
+    /// every group of near-duplicates.
+    ///
+    /// Versions of the same paper or copies with small edits share almost all their terms. The
+    /// similarity of two documents is the Jaccard similarity of the sets of terms of their
+    /// bodies, estimated with `minhash` signatures. Going down the ranking, a result is dropped
+    /// if it is at least `threshold` similar to a result that was kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `results` - The ranked results, as returned by `search`.
+    /// * `threshold` - The similarity from which documents are near-duplicates, between 0 and 1.
+    ///
+    /// # Returns
+    ///
+    /// The kept results, in their original order.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let index = load_index(Path::new("papers/.data.json"))?;
+    /// let results = index.search("neural networks", &SearchOpts::default());
+    ///
+    /// let representatives = index.collapse_near_duplicates(results, 0.9);
+    /// ```
+    pub fn collapse_near_duplicates(&self, results: Vec<Idf>, threshold: f32) -> Vec<Idf> {
+        let documents: HashMap<&Path, &Document> = self
+            .documents
+            .iter()
+            .map(|doc| (doc.path.as_path(), doc))
+            .collect();
+        let mut kept: Vec<Idf> = Vec::new();
+        let mut signatures: Vec<Vec<u64>> = Vec::new();
+
+        for result in results {
+            // Results of documents that aren't in the index have nothing to compare
+            let Some(doc) = documents.get(result.path.as_path()) else {
+                kept.push(result);
+                continue;
+            };
+            let signature = minhash(
+                doc.term_freq()
+                    .into_iter()
+                    .flat_map(|term_freq| term_freq.keys().map(String::as_str)),
+            );
+
+            if signatures
+                .iter()
+                .all(|kept| minhash_similarity(kept, &signature) < threshold)
+            {
+                signatures.push(signature);
+                kept.push(result);
+            }
+        }
+
+        kept
+    }
+
+    /// Extracts the most characteristic terms of the document at `path`.
+    ///
+    /// The terms of the body of the document are ranked by their tf-idf weight, the term
+    /// frequency in the document multiplied by the IDF of the term in the corpus, so terms that
+    /// are frequent in the document but rare elsewhere rank first. The IDF formula is taken from
+    /// `opts.idf`, and the other options don't apply. Terms with the same weight are ordered
+    /// alphabetically.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the document, as stored in the index.
+    /// * `n` - The number of terms to return.
+    /// * `opts` - The `SearchOpts` holding the IDF variant.
+    ///
+    /// # Returns
+    ///
+    /// `None` if the document isn't in the index, otherwise the `n` highest weighted terms of
+    /// the document, with their tf-idf weight as their contribution.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let index = load_index(Path::new("books/.data.json"))?;
+    ///
+    /// for keyword in index.keywords(Path::new("books/rust.pdf"), 10, &SearchOpts::default()).unwrap() {
+    ///     println!("{}: {}", keyword.term, keyword.contribution);
+    /// }
+    /// ```
+    pub fn keywords(&self, path: &Path, n: usize, opts: &SearchOpts) -> Option<Vec<TermScore>> {
+        let doc = self.documents.iter().find(|doc| doc.path == path)?;
+        let n_docs = self.documents.len();
+
+        let mut keywords: Vec<TermScore> = doc
+            .term_freq()
+            .into_iter()
+            .flatten()
+            .map(|(term, tf)| {
+                let df = self.document_frequency(term);
+                let idf = match df {
+                    0 => 0.0,
+                    df => opts.idf.idf(n_docs, df),
+                };
+                TermScore {
+                    term: term.clone(),
+                    tf: *tf,
+                    title_tf: 0.0,
+                    df,
+                    idf,
+                    contribution: tf * idf,
+                }
+            })
+            .collect();
+        keywords.sort_by(|a, b| {
+            b.contribution
+                .total_cmp(&a.contribution)
+                .then_with(|| a.term.cmp(&b.term))
+        });
+        keywords.truncate(n);
+
+        Some(keywords)
+    }
+
+    /// Finds the terms that most frequently co-occur with `term` across the corpus.
+    ///
+    /// The term frequencies of every other term are summed over the documents whose body
+    /// contains `term`, and weighted by the IDF of the term in the corpus, so terms that are
+    /// frequent in those documents but rare elsewhere rank first rather than terms that occur
+    /// everywhere. The IDF formula is taken from `opts.idf`, and the other options don't apply.
+    /// Terms with the same score are ordered alphabetically.
+    ///
+    /// # Arguments
+    ///
+    /// * `term` - The term, as tokenized.
+    /// * `n` - The number of terms to return.
+    /// * `opts` - The `SearchOpts` holding the IDF variant.
+    ///
+    /// # Returns
+    ///
+    /// The `n` terms with the highest score, with their summed term frequency as `tf` and their
+    /// score as their contribution. This is empty if no document contains `term`.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let index = load_index(Path::new("books/.data.json"))?;
+    ///
+    /// for related in index.cooccurring("borrow", 10, &SearchOpts::default()) {
+    ///     println!("{}: {}", related.term, related.contribution);
+    /// }
+    /// ```
+    pub fn cooccurring(&self, term: &str, n: usize, opts: &SearchOpts) -> Vec<TermScore> {
+        let n_docs = self.documents.len();
+        let mut totals: HashMap<&str, f32> = HashMap::new();
+        for tf in self
+            .documents
+            .iter()
+            .filter_map(Document::term_freq)
+            .filter(|tf| tf.contains_key(term))
+        {
+            for (other, freq) in tf {
+                if other != term {
+                    *totals.entry(other.as_str()).or_default() += freq;
+                }
+            }
+        }
+
+        let mut related: Vec<TermScore> = totals
+            .into_iter()
+            .map(|(other, tf)| {
+                let df = self.document_frequency(other);
+                let idf = match df {
+                    0 => 0.0,
+                    df => opts.idf.idf(n_docs, df),
+                };
+                TermScore {
+                    term: other.to_string(),
+                    tf,
+                    title_tf: 0.0,
+                    df,
+                    idf,
+                    contribution: tf * idf,
+                }
+            })
+            .collect();
+        related.sort_by(|a, b| {
+            b.contribution
+                .total_cmp(&a.contribution)
+                .then_with(|| a.term.cmp(&b.term))
+        });
+        related.truncate(n);
+
+        related
+    }
+
+    /// Finds the documents most similar to the document at `path` ("more like this").
+    ///
+    /// The document is represented by its `terms` terms with the highest tf-idf weight, which act
+    /// as an implicit query. Every other document is ranked by the cosine similarity between that
+    /// query vector and its own tf-idf vector. The IDF formula is taken from `opts.idf`, and the
+    /// other options don't apply. Titles aren't taken into account.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the document, as stored in the index.
+    /// * `terms` - The number of highest weighted terms that make up the implicit query.
+    /// * `opts` - The `SearchOpts` holding the IDF variant.
+    ///
+    /// # Returns
+    ///
+    /// `None` if the document isn't in the index, otherwise a vector of `Idf` structs holding the
+    /// path and similarity of every other document sharing a term with the query, sorted by
+    /// descending similarity. The per-term breakdown holds what every query term adds to the
+    /// similarity.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let index = load_index(Path::new("books/.data.json"))?;
+    ///
+    /// if let Some(similar) = index.similar(Path::new("books/rust.pdf"), 20, &SearchOpts::default()) {
+    ///     println!("{:?}", similar.first());
+    /// }
+    /// ```
+    pub fn similar(&self, path: &Path, terms: usize, opts: &SearchOpts) -> Option<Vec<Idf>> {
+        let source = self.documents.iter().find(|doc| doc.path == path)?;
+        let n_docs = self.documents.len();
+        let vectors = self.vectors(opts.idf);
+
+        let query: Vec<(String, f32)> = self
+            .keywords(&source.path, terms, opts)?
+            .into_iter()
+            .map(|keyword| (keyword.term, keyword.contribution))
+            .collect();
+        let query_norm = query
+            .iter()
+            .map(|(_, weight)| weight.powi(2))
+            .sum::<f32>()
+            .sqrt();
+
+        let mut idf_buff: Vec<Idf> = Vec::new();
+        for (doc, vector) in self.documents.iter().zip(vectors.iter()) {
+            if doc.path == source.path {
+                continue;
+            }
+            let doc_norm = vector.norm;
+            if doc_norm == 0.0 || query_norm == 0.0 {
+                continue;
+            }
+
+            let mut score = 0.0;
+            let mut explanation: Vec<TermScore> = Vec::new();
+            for (term, query_weight) in &query {
+                let tf = doc
+                    .term_freq()
+                    .and_then(|term_freq| term_freq.get(term))
+                    .cloned()
+                    .unwrap_or(0.0);
+                if tf == 0.0 {
+                    continue;
+                }
+                let df = self.document_frequency(term);
+                let weight = vector.weights.get(term).copied().unwrap_or(0.0);
+                let contribution = query_weight * weight / (query_norm * doc_norm);
+
+                score += contribution;
+                explanation.push(TermScore {
+                    term: term.clone(),
+                    tf,
+                    title_tf: 0.0,
+                    df,
+                    idf: opts.idf.idf(n_docs, df),
+                    contribution,
+                });
+            }
+
+            if score > 0.0 {
+                idf_buff.push(Idf {
+                    path: doc.path.clone(),
+                    tf: score,
+                    explanation,
+                    pages: Vec::new(),
+                    comparison: None,
+                });
+            }
+        }
+
+        idf_buff.sort_by(|a, b| b.tf.total_cmp(&a.tf));
+        Some(idf_buff)
+    }
+
+    /// Calculates the cosine similarity between every pair of documents.
+    ///
+    /// Every document is represented by the tf-idf vector of its body, like the documents ranked
+    /// by `similar`, but with all of its terms. The similarities are summed over an inverted index
+    /// of the normalized vectors, so only pairs sharing a term are compared, and with `neighbors`
+    /// only that many most similar documents are kept per document, so the result doesn't grow
+    /// quadratically with the corpus.
+    ///
+    /// # Arguments
+    ///
+    /// * `idf` - The formula used to calculate the inverse document frequency.
+    /// * `neighbors` - The number of most similar documents to keep for every document, or `None`
+    ///   to keep all of them.
+    ///
+    /// # Returns
+    ///
+    /// For every document, in the order of `documents`, the index and similarity of the other
+    /// documents with a nonzero similarity to it, by descending similarity.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let index = load_index(Path::new("books/.data.json"))?;
+    ///
+    /// for (doc, neighbors) in index.documents.iter().zip(index.document_similarities(IdfVariant::Smooth, Some(3))) {
+    ///     println!("{:?}: {:?}", doc.path, neighbors);
+    /// }
+    /// ```
+    pub fn document_similarities(
+        &self,
+        idf: IdfVariant,
+        neighbors: Option<usize>,
+    ) -> Vec<Vec<(usize, f32)>> {
+        let cached = self.vectors(idf);
+        let mut vectors: Vec<Vec<(&str, f32)>> = Vec::with_capacity(cached.len());
+        let mut postings: HashMap<&str, Vec<(usize, f32)>> = HashMap::new();
+
+        for (idx, vector) in cached.iter().enumerate() {
+            let normalized: Vec<(&str, f32)> = vector
+                .weights
+                .iter()
+                .map(|(term, weight)| (term.as_str(), weight / vector.norm))
+                .collect();
+            for (term, weight) in &normalized {
+                postings.entry(term).or_default().push((idx, *weight));
+            }
+            vectors.push(normalized);
+        }
+
+        vectors
+            .iter()
+            .enumerate()
+            .map(|(idx, vector)| {
+                let mut similarities: HashMap<usize, f32> = HashMap::new();
+                for (term, weight) in vector {
+                    for (other, other_weight) in &postings[term] {
+                        if *other != idx {
+                            *similarities.entry(*other).or_insert(0.0) += weight * other_weight;
+                        }
+                    }
+                }
+
+                let mut row: Vec<(usize, f32)> = similarities.into_iter().collect();
+                row.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+                if let Some(neighbors) = neighbors {
+                    row.truncate(neighbors);
+                }
+                row
+            })
+            .collect()
+    }
+
+    /// Searches the index like `search`, but returns the results as an iterator.
+    ///
+    /// The results are kept in a binary heap and only ordered as they are taken, so taking the
+    /// first few results of a large result set doesn't sort all of them.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let index = load_index(Path::new("books/.data.json"))?;
+    ///
+    /// let best: Vec<Idf> = index.search_iter("rust", &SearchOpts::default()).take(10).collect();
+    /// ```
+    pub fn search_iter(&self, query: &str, opts: &SearchOpts) -> SearchResults<'_> {
+        self.search_iter_with(query, opts, &TfIdfScorer { idf: opts.idf })
+    }
+
+    /// Searches the index like `search_with`, but returns the results as an iterator, see
+    /// `search_iter`.
+    pub fn search_iter_with(
+        &self,
+        query: &str,
+        opts: &SearchOpts,
+        scorer: &dyn Scorer,
+    ) -> SearchResults<'_> {
+        SearchResults {
+            heap: self.ranked(query, opts, scorer).into(),
+        }
+    }
+
+    /// Searches the index like `search`, but only returns the `k` best results.
+    ///
+    /// Only the best `k` results are kept while scoring, in a binary heap of size `k`, so the
+    /// whole result set is never sorted. The results are the first `k` of `search`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::path::PathBuf;
+    /// use tf_idf::lexer::index::{Index, IndexSettings, SearchOpts, Tiebreak};
+    /// use tf_idf::lexer::lib::index_text;
+    ///
+    /// let documents = ["rust", "rust code", "rust", "go code", "rust rust"]
+    ///     .iter()
+    ///     .enumerate()
+    ///     .map(|(i, text)| index_text(PathBuf::from(format!("{}.txt", 5 - i)), text))
+    ///     .collect();
+    /// let index = Index::new(IndexSettings::default(), documents);
+    ///
+    /// for tiebreak in [Tiebreak::None, Tiebreak::Path] {
+    ///     let opts = SearchOpts {
+    ///         tiebreak,
+    ///         ..SearchOpts::default()
+    ///     };
+    ///     let top: Vec<PathBuf> = index.search_top("rust", &opts, 3).into_iter().map(|idf| idf.path).collect();
+    ///     let all: Vec<PathBuf> = index.search("rust", &opts).into_iter().map(|idf| idf.path).collect();
+    ///     assert_eq!(top, all[..3]);
+    /// }
+    /// ```
+    pub fn search_top(&self, query: &str, opts: &SearchOpts, k: usize) -> Vec<Idf> {
+        self.search_top_with(query, opts, &TfIdfScorer { idf: opts.idf }, k)
+    }
+
+    /// Searches the index like `search_with`, but only returns the `k` best results, see
+    /// `search_top`.
+    pub fn search_top_with(
+        &self,
+        query: &str,
+        opts: &SearchOpts,
+        scorer: &dyn Scorer,
+        k: usize,
+    ) -> Vec<Idf> {
+        let mut heap: BinaryHeap<Reverse<Ranked>> = BinaryHeap::with_capacity(k + 1);
+
+        for ranked in self.ranked(query, opts, scorer) {
+            heap.push(Reverse(ranked));
+            if heap.len() > k {
+                heap.pop();
+            }
+        }
+
+        heap.into_sorted_vec()
+            .into_iter()
+            .map(|Reverse(ranked)| ranked.idf)
+            .collect()
+    }
+}
+
+/// An `Idf` with what it is ranked by, so results can be ranked in a `BinaryHeap`.
+///
+/// The result that ranks first is the greatest: the one with the best score in the `order`, then
+/// the first by the `tiebreak`, then the one whose document comes first in the index. This is
+/// a total order, so every way of ranking the results gives the same order as `Index::search`.
+#[derive(Debug)]
+struct Ranked<'a> {
+    idf: Idf,
+    doc: Option<&'a Document>,
+    position: usize,
+    order: Order,
+    tiebreak: Tiebreak,
+}
+
+impl PartialEq for Ranked<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Ranked<'_> {}
+
+impl PartialOrd for Ranked<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Ranked<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let ordering = self.idf.tf.total_cmp(&other.idf.tf);
+        let ordering = match self.order {
+            Order::Desc => ordering,
+            Order::Asc => ordering.reverse(),
+        };
+        ordering
+            .then_with(|| match (self.doc, other.doc) {
+                (Some(a), Some(b)) => self.tiebreak.compare(a, b).reverse(),
+                _ => Ordering::Equal,
+            })
+            .then_with(|| other.position.cmp(&self.position))
+    }
+}
+
+/// An iterator over search results in the order of `Index::search`, returned by
+/// `Index::search_iter`.
+#[derive(Debug)]
+pub struct SearchResults<'a> {
+    heap: BinaryHeap<Ranked<'a>>,
+}
+
+impl Iterator for SearchResults<'_> {
+    type Item = Idf;
+
+    fn next(&mut self) -> Option<Idf> {
+        self.heap.pop().map(|ranked| ranked.idf)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.heap.len(), Some(self.heap.len()))
+    }
+}
+
+/// Represents a term of a query, optionally scoped to a field of the documents.
+///
+/// # Fields
+///
+/// * `field` - The lowercase name of the field the term must occur in, such as `title`, `body`
+///   or `author`, or `None` to match the body and the title like an ordinary query term.
+/// * `term` - The tokenized term.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct QueryTerm {
+    pub field: Option<String>,
+    pub term: String,
+}
+
+impl fmt::Display for QueryTerm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.field {
+            Some(field) => write!(f, "{}:{}", field, self.term),
+            None => write!(f, "{}", self.term),
+        }
+    }
+}
+
+/// Parses a query into its terms, scoping the terms written as `field:term` to that field.
+///
+/// A whitespace separated word of the query is scoped if it has the form `field:value` and
+/// `field` is `title`, `body` or the name of a field of one of the documents, ignoring case. The
+/// value is tokenized like the rest of the query, and every term it yields is scoped to the
+/// field. Other words, including ones with a colon but an unknown field name, are ordinary
+/// query terms.
+///
+/// # Arguments
+///
+/// * `query` - The query to parse.
+/// * `tokenizer` - The `Tokenizer` the documents were indexed with.
+/// * `documents` - The documents of the index, whose fields can be searched.
+///
+/// # Returns
+///
+/// The terms of the query, in query order.
+///
+/// # Examples
+///
+/// ```ignore
+/// let terms = parse_query("author:smith rust", &Tokenizer::default(), &index.documents);
+///
+/// assert_eq!(terms[0].to_string(), "author:smith");
+/// assert_eq!(terms[1].field, None);
+/// ```
+pub fn parse_query(query: &str, tokenizer: &Tokenizer, documents: &[Document]) -> Vec<QueryTerm> {
+    let fields: HashSet<&str> = documents
+        .iter()
+        .flat_map(|doc| doc.fields.keys().map(String::as_str))
+        .chain(["title", "body"])
+        .collect();
+    let mut terms: Vec<QueryTerm> = Vec::new();
+
+    for word in query.split_whitespace() {
+        let (field, value) = match word.split_once(':') {
+            Some((field, value)) if fields.contains(field.to_lowercase().as_str()) => {
+                (Some(field.to_lowercase()), value)
+            }
+            _ => (None, word),
+        };
+        terms.extend(tokenizer.tokenize(value).into_iter().map(|term| QueryTerm {
+            field: field.clone(),
+            term,
+        }));
+    }
+
+    terms
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Index, IndexSettings, SearchOpts};
+    use crate::lexer::lexing::{index_field_data, Document, TfCap};
+    use crate::lexer::lib::index_text;
+    use std::path::{Path, PathBuf};
+
+    fn document(path: &str, title: Option<&str>, body: &str) -> Document {
+        let mut document = index_text(PathBuf::from(path), body);
+        let tokenizer = IndexSettings::default().tokenizer;
+        if let Some(title) = title {
+            let body = tokenizer.tokenize(body);
+            document.title = Some(title.to_string());
+            document.title_data = index_field_data(tokenizer.tokenize(title), &body, TfCap::None);
+        }
+        document
+    }
+
+    #[test]
+    fn title_boost_does_not_outrank_a_body_heavy_document() {
+        let body_heavy = format!("{} {}", "rust ".repeat(50), "memory ".repeat(950));
+        let index = Index::new(
+            IndexSettings::default(),
+            vec![
+                document("title.txt", Some("Rust"), &"lorem ".repeat(1000)),
+                document("body.txt", None, &body_heavy),
+                document("both.txt", Some("Rust"), &body_heavy),
+                document("other.txt", None, "unrelated"),
+            ],
+        );
+
+        let results = index.search("rust", &SearchOpts::default());
+        let paths: Vec<&Path> = results.iter().map(|idf| idf.path.as_path()).collect();
+        assert_eq!(
+            paths,
+            [
+                Path::new("both.txt"),
+                Path::new("body.txt"),
+                Path::new("title.txt")
+            ]
+        );
+    }
+}
@@ -1,7 +1,13 @@
+use jieba_rs::Jieba;
+use regex::{Captures, Regex};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use std::sync::OnceLock;
 use std::time::SystemTime;
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
 
 /// Represents a mapping of terms to their frequencies.
 pub type TermFreq = HashMap<String, f32>;
@@ -9,6 +15,9 @@ pub type TermFreq = HashMap<String, f32>;
 /// Represents a mapping of document paths to their term frequencies.
 pub type DocFreq = HashMap<PathBuf, TermFreq>;
 
+/// Represents a mapping of terms to the sorted page numbers, starting at 1, they occur on.
+pub type TermPages = HashMap<String, Vec<usize>>;
+
 pub trait DocFreqExt {
     fn single(path: PathBuf, term_freq: TermFreq) -> DocFreq;
 }
@@ -31,17 +40,40 @@ impl DocFreqExt for DocFreq {
 /// * `data` - A `DocFreq` representing the mapping of document paths to their term frequencies.
 /// * `path` - A `PathBuf` representing the path of the document.
 /// * `last_modified` - A `SystemTime` representing the last modified time of the document.
+/// * `modified` - The modification time of the file, if known, used to favor recent documents.
+/// * `tokens` - The ordered token list of the document, only stored when requested since it
+///   significantly increases the index size.
+/// * `title` - The title of the document taken from its metadata, if any.
+/// * `title_data` - A `TermFreq` of the title tokens, indexed separately from the body but on the
+///   scale of the body, see `index_field_data`.
+/// * `pages` - The `TermPages` of the document, only stored when requested and for documents
+///   with pages.
+/// * `len` - The number of terms in the body of the document.
+/// * `fields` - A `TermFreq` for each metadata field of the document other than the title, such
+///   as the author, used for field-scoped queries like `author:smith`.
+/// * `raw_words` - The distinct words of the body as they are written, before normalization,
+///   only stored when requested. See `raw_words`.
 ///
 /// # Examples
 ///
 /// ```
+/// use std::collections::HashMap;
 /// use std::path::PathBuf;
 /// use std::time::SystemTime;
+/// use tf_idf::lexer::lexing::{DocFreq, Document, TermFreq};
 ///
 /// let document = Document {
 ///     data: DocFreq::new(),
 ///     path: PathBuf::from("path/to/document.txt"),
 ///     last_modified: SystemTime::now(),
+///     modified: None,
+///     tokens: None,
+///     title: None,
+///     title_data: TermFreq::new(),
+///     pages: None,
+///     len: 0,
+///     fields: HashMap::new(),
+///     raw_words: None,
 /// };
 ///
 /// println!("{:?}", document);
@@ -51,12 +83,97 @@ pub struct Document {
     pub data: DocFreq,
     pub path: PathBuf,
     pub last_modified: SystemTime,
+    #[serde(default)]
+    pub modified: Option<SystemTime>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tokens: Option<Vec<String>>,
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub title_data: TermFreq,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pages: Option<TermPages>,
+    #[serde(default)]
+    pub len: usize,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub fields: HashMap<String, TermFreq>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_words: Option<HashSet<String>>,
+}
+
+impl Document {
+    /// Returns the term frequencies of the document's own path, if present.
+    pub fn term_freq(&self) -> Option<&TermFreq> {
+        self.data.get(&self.path)
+    }
+
+    /// Returns the sorted, deduplicated page numbers any of the `terms` occur on.
+    ///
+    /// This is empty if the pages of the document weren't stored.
+    pub fn pages_of(&self, terms: &[String]) -> Vec<usize> {
+        let mut pages: Vec<usize> = terms
+            .iter()
+            .filter_map(|term| self.pages.as_ref()?.get(term))
+            .flatten()
+            .copied()
+            .collect();
+        pages.sort_unstable();
+        pages.dedup();
+        pages
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Idf {
     pub path: PathBuf,
     pub tf: f32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub explanation: Vec<TermScore>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pages: Vec<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comparison: Option<ScorerComparison>,
+}
+
+/// Represents the contribution of a single query term to the score of a document.
+///
+/// # Fields
+///
+/// * `term` - The query term, written as `field:term` if it was scoped to a field.
+/// * `tf` - The frequency of the term in the body of the document, or in the field the term was
+///   scoped to.
+/// * `title_tf` - The frequency of the term in the title of the document, on the scale of the
+///   body, see `index_field_data`. Zero for terms scoped to a field.
+/// * `df` - The number of documents the term occurs in.
+/// * `idf` - The inverse document frequency of the term, calculated with the IDF variant of the
+///   search.
+/// * `contribution` - What the term adds to the score, before proximity and normalization.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TermScore {
+    pub term: String,
+    pub tf: f32,
+    pub title_tf: f32,
+    pub df: usize,
+    pub idf: f32,
+    pub contribution: f32,
+}
+
+/// Represents the score of a document under each of the built-in ranking formulas, for comparing
+/// them on the same query.
+///
+/// The scores are calculated like the score of the search, with the same title boost, operator,
+/// boosts and query normalization, only with a different `Scorer`.
+///
+/// # Fields
+///
+/// * `tf` - The score with the `TfScorer`, the term frequencies alone.
+/// * `tf_idf` - The score with the `TfIdfScorer`, using the IDF variant of the search.
+/// * `bm25` - The score with the `Bm25Scorer` with its default parameters.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ScorerComparison {
+    pub tf: f32,
+    pub tf_idf: f32,
+    pub bm25: f32,
 }
 
 impl PartialEq for Idf {
@@ -65,15 +182,50 @@ impl PartialEq for Idf {
     }
 }
 
+/// Represents how much repeating a term in a document counts.
+///
+/// # Variants
+///
+/// * `None` - The frequency of a term is its count divided by the number of terms in the
+///   document. This is the default.
+/// * `Count` - The count of a term is capped at the given maximum before it is divided by the
+///   number of terms, so a document stuffed with a keyword can't dominate the ranking.
+/// * `Augmented` - The augmented frequency `0.5 + 0.5 * count / max_count`, where `max_count` is
+///   the count of the most frequent term in the document.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TfCap {
+    #[default]
+    None,
+    Count(usize),
+    Augmented,
+}
+
+impl FromStr for TfCap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(TfCap::None),
+            "augmented" => Ok(TfCap::Augmented),
+            count => match count.parse() {
+                Ok(count) if count > 0 => Ok(TfCap::Count(count)),
+                _ => Err(format!("Unknown term frequency cap: {}", s)),
+            },
+        }
+    }
+}
+
 /// Indexes data by calculating the term frequencies.
 ///
 /// This function takes a vector of strings (`Vec<String>`) representing the content to be indexed.
-/// It calculates the term frequencies for each term in the content and returns a `TermFreq` mapping
-/// the terms to their corresponding frequencies.
+/// It counts each term in the content and turns the counts into frequencies according to `tf_cap`,
+/// returning a `TermFreq` mapping the terms to their corresponding frequencies.
 ///
 /// # Arguments
 ///
 /// * `content` - A vector of strings (`Vec<String>`) representing the content to be indexed.
+/// * `tf_cap` - The `TfCap` limiting how much repeating a term counts.
 ///
 /// # Returns
 ///
@@ -82,6 +234,8 @@ impl PartialEq for Idf {
 /// # Examples
 ///
 /// ```
+/// use tf_idf::lexer::lexing::{index_data, TfCap};
+///
 /// let content = vec![
 ///     String::from("apple"),
 ///     String::from("banana"),
@@ -90,33 +244,103 @@ impl PartialEq for Idf {
 ///     String::from("apple"),
 /// ];
 ///
-/// let term_freq = index_data(content);
+/// let term_freq = index_data(content.clone(), TfCap::None);
 ///
 /// assert_eq!(term_freq["apple"], 0.6);
 /// assert_eq!(term_freq["banana"], 0.2);
 /// assert_eq!(term_freq["orange"], 0.2);
+///
+/// let capped = index_data(content.clone(), TfCap::Count(2));
+/// assert_eq!(capped["apple"], 0.4);
+///
+/// let augmented = index_data(content, TfCap::Augmented);
+/// assert_eq!(augmented["apple"], 1.0);
 /// ```
-pub fn index_data(content: Vec<String>) -> TermFreq {
-    let mut data: TermFreq = HashMap::new();
+pub fn index_data(content: Vec<String>, tf_cap: TfCap) -> TermFreq {
     let full_length = content.len() as f32;
+    let mut counts: HashMap<String, usize> = HashMap::new();
 
     for term in content {
-        if data.contains_key(&term) {
-            *data.get_mut(&term).unwrap() += 1.0 / full_length;
-        } else {
-            data.insert(term, 1.0);
+        *counts.entry(term).or_insert(0) += 1;
+    }
+
+    let max_count = counts.values().copied().max().unwrap_or(0) as f32;
+
+    counts
+        .into_iter()
+        .map(|(term, count)| {
+            let freq = match tf_cap {
+                TfCap::None => count as f32 / full_length,
+                TfCap::Count(max) => count.min(max) as f32 / full_length,
+                TfCap::Augmented => 0.5 + 0.5 * count as f32 / max_count,
+            };
+            (term, freq)
+        })
+        .collect()
+}
+
+/// Indexes a field of a document, such as its title, on the scale of the body.
+///
+/// The frequencies of `index_data` are relative to the length of the content, so the one term of
+/// a one word title would have a frequency of 1, far above any term of a long body. Here a term
+/// counts as much per occurrence as it does in the body instead: its count is divided by the
+/// number of terms in the body, or with `TfCap::Augmented` it adds `0.5 / max_count` per
+/// occurrence, where `max_count` is the count of the most frequent term in the body.
+///
+/// # Arguments
+///
+/// * `content` - The terms of the field.
+/// * `body` - The terms of the body of the document.
+/// * `tf_cap` - The `TfCap` limiting how much repeating a term counts.
+///
+/// # Examples
+///
+/// ```
+/// use tf_idf::lexer::lexing::{index_field_data, TfCap};
+///
+/// let title = vec![String::from("apple")];
+/// let body: Vec<String> = ["apple", "banana", "apple", "orange"].map(String::from).to_vec();
+///
+/// assert_eq!(index_field_data(title.clone(), &body, TfCap::None)["apple"], 0.25);
+/// assert_eq!(index_field_data(title, &body, TfCap::Augmented)["apple"], 0.25);
+/// ```
+pub fn index_field_data(content: Vec<String>, body: &[String], tf_cap: TfCap) -> TermFreq {
+    let body_len = body.len().max(1) as f32;
+    let body_max_count = match tf_cap {
+        TfCap::Augmented => {
+            let mut counts: HashMap<&String, usize> = HashMap::new();
+            for term in body {
+                *counts.entry(term).or_insert(0) += 1;
+            }
+            counts.values().copied().max().unwrap_or(1) as f32
         }
+        _ => 1.0,
+    };
+    let mut counts: HashMap<String, usize> = HashMap::new();
+
+    for term in content {
+        *counts.entry(term).or_insert(0) += 1;
     }
 
-    data
+    counts
+        .into_iter()
+        .map(|(term, count)| {
+            let freq = match tf_cap {
+                TfCap::None => count as f32 / body_len,
+                TfCap::Count(max) => count.min(max) as f32 / body_len,
+                TfCap::Augmented => 0.5 * count as f32 / body_max_count,
+            };
+            (term, freq)
+        })
+        .collect()
 }
 
 /// Splits the input string into individual words based on specified delimiters.
 ///
 /// This function takes an input string and splits it into individual words based on the specified
-/// delimiters. The delimiters used for splitting include: single quote ('), period (.), closing
-/// parenthesis (')', opening parenthesis ('('), backtick ('`'), comma (,), double quote ("), space (' '),
-/// and newline ('\n').
+/// delimiters. The delimiters used for splitting include: single quote (') or typographic apostrophe
+/// (’), period (.), closing parenthesis (')', opening parenthesis ('('), backtick ('`'), comma (,),
+/// double quote ("), space (' '), and newline ('\n').
 ///
 /// # Arguments
 ///
@@ -129,17 +353,47 @@ pub fn index_data(content: Vec<String>) -> TermFreq {
 /// # Examples
 ///
 /// ```
-/// let input = "Hello, World! How are you today?";
+/// use tf_idf::lexer::lexing::split_into_words;
+///
+/// let input = "Hello, World. How are \"you\" today";
 ///
 /// let result = split_into_words(input);
 ///
-/// assert_eq!(result, vec!["hello", "world", "how", "are", "you", "today"]);
+/// assert_eq!(result, vec!["Hello", "World", "How", "are", "you", "today"]);
 /// ```
 pub fn split_into_words(input: &str) -> Vec<String> {
-    let delimiters = ['\'', '.', ')', '(', '`', ',', '"', ' ', '\n'];
+    split_on(input, &['\'', '.', ')', '(', '`', ',', '"', ' ', '\n'])
+}
 
+/// Collects the distinct words of the input string exactly as they are written.
+///
+/// The input is split on whitespace and the delimiters of `split_into_words`, but unlike terms
+/// the words aren't lowercased or otherwise normalized, so "Rust" and "rust" are different words.
+/// Used for the exact-match boost, see `exact_factor`.
+///
+/// # Examples
+///
+/// ```
+/// use tf_idf::lexer::lexing::raw_words;
+///
+/// let words = raw_words("Rust (the language), not rust.");
+///
+/// assert!(words.contains("Rust") && words.contains("rust"));
+/// assert!(!words.contains("language),"));
+/// ```
+pub fn raw_words(input: &str) -> HashSet<String> {
+    input
+        .replace('\u{2019}', "'")
+        .split(|c: char| c.is_whitespace() || ['\'', '.', ')', '(', '`', ',', '"'].contains(&c))
+        .filter(|word| !word.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Splits the input string on any of the `delimiters`, dropping empty words.
+fn split_on(input: &str, delimiters: &[char]) -> Vec<String> {
     let mut result = input
-        .to_lowercase()
+        .replace('\u{2019}', "'")
         .split(|c| delimiters.contains(&c))
         .filter(|word| !word.is_empty())
         .map(String::from)
@@ -148,3 +402,963 @@ pub fn split_into_words(input: &str) -> Vec<String> {
     result.shrink_to_fit();
     result
 }
+
+/// Extracts a snippet of context around the first occurrence of a term.
+///
+/// This function takes the ordered tokens of a document and returns the tokens surrounding
+/// the first occurrence of `term`, joined by spaces. Up to `radius` tokens are taken on each side.
+///
+/// # Arguments
+///
+/// * `tokens` - The ordered tokens of the document.
+/// * `term` - The term to search for.
+/// * `radius` - The number of tokens to include before and after the term.
+///
+/// # Returns
+///
+/// `Some(String)` with the snippet, or `None` if the term doesn't occur in the tokens.
+///
+/// # Examples
+///
+/// ```
+/// use tf_idf::lexer::lexing::{snippet, split_into_words};
+///
+/// let tokens = split_into_words("the quick brown fox jumps over the lazy dog");
+///
+/// assert_eq!(snippet(&tokens, "fox", 2), Some("quick brown fox jumps over".to_string()));
+/// assert_eq!(snippet(&tokens, "cat", 2), None);
+/// ```
+pub fn snippet(tokens: &[String], term: &str, radius: usize) -> Option<String> {
+    let position = tokens.iter().position(|token| token == term)?;
+    let start = position.saturating_sub(radius);
+    let end = (position + radius + 1).min(tokens.len());
+
+    Some(tokens[start..end].join(" "))
+}
+
+/// Extracts the passage of a document that best matches the query.
+///
+/// This function slides a window of `2 * radius + 1` tokens over the ordered tokens of a document
+/// and returns the window in which the query terms weigh the most, joined by spaces. Every
+/// occurrence of a term in the window adds its weight, usually its IDF, so the window sums the
+/// tf-idf of the query terms within it. Of equally good windows the first passage is returned,
+/// centered on the matching terms.
+///
+/// # Arguments
+///
+/// * `tokens` - The ordered tokens of the document.
+/// * `weights` - The weight of every query term.
+/// * `radius` - The number of tokens on each side of the middle of the window.
+///
+/// # Returns
+///
+/// `Some(String)` with the snippet, or `None` if no query term occurs in the tokens.
+///
+/// # Examples
+///
+/// ```
+/// use std::collections::HashMap;
+/// use tf_idf::lexer::lexing::{best_snippet, split_into_words};
+///
+/// let tokens = split_into_words("rust is fast and the rust memory model is safe");
+/// let weights = HashMap::from([("rust".to_string(), 1.0), ("memory".to_string(), 2.0)]);
+///
+/// assert_eq!(best_snippet(&tokens, &weights, 1), Some("the rust memory".to_string()));
+/// ```
+pub fn best_snippet(
+    tokens: &[String],
+    weights: &HashMap<String, f32>,
+    radius: usize,
+) -> Option<String> {
+    let width = (2 * radius + 1).min(tokens.len());
+    let weight = |token: &String| weights.get(token).copied().unwrap_or(0.0);
+
+    // The first run of consecutive windows with the highest sum, as (first, last, sum)
+    let (first, last, best_sum) = (0..=tokens.len() - width)
+        .map(|start| {
+            let sum: f32 = tokens[start..start + width].iter().map(weight).sum();
+            (start, sum)
+        })
+        .fold((0, 0, 0.0), |(first, last, best_sum), (start, sum)| {
+            if sum > best_sum {
+                (start, start, sum)
+            } else if sum == best_sum && start == last + 1 {
+                (first, start, best_sum)
+            } else {
+                (first, last, best_sum)
+            }
+        });
+
+    if best_sum <= 0.0 {
+        return None;
+    }
+    let start = (first + last) / 2;
+    Some(tokens[start..start + width].join(" "))
+}
+
+/// Represents how the snippet under a search result is chosen.
+///
+/// # Variants
+///
+/// * `Best` - The passage with the highest summed tf-idf of the query terms, see `best_snippet`.
+///   This is the default.
+/// * `First` - The passage around the first occurrence of a query term, see `snippet`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SnippetStrategy {
+    #[default]
+    Best,
+    First,
+}
+
+impl FromStr for SnippetStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "best" => Ok(SnippetStrategy::Best),
+            "first" => Ok(SnippetStrategy::First),
+            _ => Err(format!("Unknown snippet strategy: {}", s)),
+        }
+    }
+}
+
+/// Common English words that carry little meaning on their own and are filtered out when
+/// `--builtin-stopwords` is given.
+pub const ENGLISH_STOPWORDS: &[&str] = &[
+    "a",
+    "about",
+    "above",
+    "after",
+    "again",
+    "against",
+    "all",
+    "am",
+    "an",
+    "and",
+    "any",
+    "are",
+    "as",
+    "at",
+    "be",
+    "because",
+    "been",
+    "before",
+    "being",
+    "below",
+    "between",
+    "both",
+    "but",
+    "by",
+    "can",
+    "did",
+    "do",
+    "does",
+    "doing",
+    "down",
+    "during",
+    "each",
+    "few",
+    "for",
+    "from",
+    "further",
+    "had",
+    "has",
+    "have",
+    "having",
+    "he",
+    "her",
+    "here",
+    "hers",
+    "herself",
+    "him",
+    "himself",
+    "his",
+    "how",
+    "i",
+    "if",
+    "in",
+    "into",
+    "is",
+    "it",
+    "its",
+    "itself",
+    "just",
+    "me",
+    "more",
+    "most",
+    "my",
+    "myself",
+    "no",
+    "nor",
+    "not",
+    "now",
+    "of",
+    "off",
+    "on",
+    "once",
+    "only",
+    "or",
+    "other",
+    "our",
+    "ours",
+    "ourselves",
+    "out",
+    "over",
+    "own",
+    "same",
+    "she",
+    "should",
+    "so",
+    "some",
+    "such",
+    "than",
+    "that",
+    "the",
+    "their",
+    "theirs",
+    "them",
+    "themselves",
+    "then",
+    "there",
+    "these",
+    "they",
+    "this",
+    "those",
+    "through",
+    "to",
+    "too",
+    "under",
+    "until",
+    "up",
+    "very",
+    "was",
+    "we",
+    "were",
+    "what",
+    "when",
+    "where",
+    "which",
+    "while",
+    "who",
+    "whom",
+    "why",
+    "will",
+    "with",
+    "you",
+    "your",
+    "yours",
+    "yourself",
+    "yourselves",
+];
+
+/// Represents the set of words filtered out by the `Tokenizer`.
+///
+/// Only the `hash` of the set is serialized, so the index records which list it was built with
+/// without storing the list itself. Two sets are equal when their hashes are equal. A
+/// deserialized set therefore has no words and must not be used for tokenizing.
+///
+/// # Fields
+///
+/// * `words` - The lowercase words to filter out.
+/// * `hash` - A stable FNV-1a hash of the sorted words.
+///
+/// # Examples
+///
+/// ```
+/// use tf_idf::lexer::lexing::Stopwords;
+///
+/// let stopwords = Stopwords::new(["the", "of"].map(String::from));
+///
+/// assert!(stopwords.contains("the"));
+/// assert_eq!(stopwords, Stopwords::new(["of", "the"].map(String::from)));
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Stopwords {
+    #[serde(skip)]
+    words: HashSet<String>,
+    hash: u64,
+}
+
+impl Stopwords {
+    /// Creates a set of stop words from the given words, lowercasing them and skipping
+    /// empty ones.
+    pub fn new(words: impl IntoIterator<Item = String>) -> Stopwords {
+        let words: HashSet<String> = words
+            .into_iter()
+            .map(|word| word.trim().to_lowercase())
+            .filter(|word| !word.is_empty())
+            .collect();
+
+        let mut sorted: Vec<&String> = words.iter().collect();
+        sorted.sort();
+
+        // FNV-1a, since the hash is saved in the index and must not change between builds
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for byte in sorted.iter().flat_map(|word| word.bytes().chain([b'\n'])) {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+
+        Stopwords { words, hash }
+    }
+
+    /// Returns whether `word` is a stop word.
+    pub fn contains(&self, word: &str) -> bool {
+        self.words.contains(word)
+    }
+
+    /// Returns whether the set has no words.
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+}
+
+impl Default for Stopwords {
+    fn default() -> Self {
+        Stopwords::new(Vec::new())
+    }
+}
+
+impl PartialEq for Stopwords {
+    fn eq(&self, other: &Self) -> bool {
+        self.hash == other.hash
+    }
+}
+
+/// Represents how apostrophes inside words are handled.
+///
+/// # Variants
+///
+/// * `Split` - Apostrophes split words, so "don't" becomes "don" and "t". This is the default.
+/// * `StripPossessive` - A possessive "'s" is stripped, so "company's" becomes "company", and
+///   contractions such as "don't" are kept intact.
+/// * `Keep` - Words are kept intact, apostrophes included.
+///
+/// In the last two variants quotes around a word are removed, so "'hello'" becomes "hello".
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Apostrophes {
+    #[default]
+    Split,
+    StripPossessive,
+    Keep,
+}
+
+impl Apostrophes {
+    /// Splits the input string into words like `split_into_words`, handling apostrophes
+    /// according to the variant.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tf_idf::lexer::lexing::Apostrophes;
+    ///
+    /// let input = "The company's 'policy' doesn't apply";
+    ///
+    /// assert_eq!(
+    ///     Apostrophes::StripPossessive.split(input),
+    ///     vec!["The", "company", "policy", "doesn't", "apply"]
+    /// );
+    /// ```
+    pub fn split(&self, input: &str) -> Vec<String> {
+        if *self == Apostrophes::Split {
+            return split_into_words(input);
+        }
+
+        split_on(input, &['.', ')', '(', '`', ',', '"', ' ', '\n'])
+            .into_iter()
+            .filter_map(|word| {
+                let word = word.trim_matches('\'');
+                let word = match self {
+                    Apostrophes::StripPossessive => word
+                        .strip_suffix("'s")
+                        .or_else(|| word.strip_suffix("'S"))
+                        .unwrap_or(word),
+                    _ => word,
+                };
+
+                (!word.is_empty()).then(|| word.to_string())
+            })
+            .collect()
+    }
+}
+
+impl FromStr for Apostrophes {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "split" => Ok(Apostrophes::Split),
+            "strip-possessive" => Ok(Apostrophes::StripPossessive),
+            "keep" => Ok(Apostrophes::Keep),
+            _ => Err(format!("Unknown apostrophe handling: {}", s)),
+        }
+    }
+}
+
+/// Represents how URLs and email addresses are tokenized.
+///
+/// # Variants
+///
+/// * `Split` - URLs and email addresses are split like any other text, into fragments such as
+///   "https:/" and "com". This is the default.
+/// * `Keep` - Every URL and email address is kept as a single token.
+/// * `Strip` - URLs and email addresses are removed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Links {
+    #[default]
+    Split,
+    Keep,
+    Strip,
+}
+
+impl FromStr for Links {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "split" => Ok(Links::Split),
+            "keep" => Ok(Links::Keep),
+            "strip" => Ok(Links::Strip),
+            _ => Err(format!("Unknown link handling: {}", s)),
+        }
+    }
+}
+
+/// Returns the regex matching URLs and email addresses, compiled once.
+fn link_regex() -> &'static Regex {
+    static LINK: OnceLock<Regex> = OnceLock::new();
+
+    LINK.get_or_init(|| {
+        Regex::new(
+            r#"(?i)\b(?:https?://|www\.)[^\s"'<>()\[\]]+|\b[a-z0-9._%+-]+@[a-z0-9.-]+\.[a-z]{2,}\b"#,
+        )
+        .expect("Invalid link regex")
+    })
+}
+
+/// Returns the regex matching dotted abbreviations of single letters, compiled once.
+fn abbreviation_regex() -> &'static Regex {
+    static ABBREVIATION: OnceLock<Regex> = OnceLock::new();
+
+    ABBREVIATION.get_or_init(|| {
+        Regex::new(r"\b\p{L}(?:\.\p{L})+\b\.?").expect("Invalid abbreviation regex")
+    })
+}
+
+/// Returns the regex matching decimal and version numbers, compiled once.
+///
+/// A number is a word with a digit followed by one or more groups of a period and digits, such as
+/// "3.14", "1.2.3" or "v2.0rc1". A period ending a sentence isn't part of the number, and
+/// `Tokenizer::split` trims the other punctuation that may end one.
+fn decimal_regex() -> &'static Regex {
+    static DECIMAL: OnceLock<Regex> = OnceLock::new();
+
+    DECIMAL.get_or_init(|| {
+        Regex::new(r#"[^\s.,()`"']*\d(?:\.\d+)+[^\s.,()`"']*"#).expect("Invalid decimal regex")
+    })
+}
+
+/// Removes the periods of dotted abbreviations, so they aren't split into single letters.
+///
+/// An abbreviation is a run of single letters separated by periods, such as "U.S.A." or "e.g.",
+/// with or without a final period. Joining the letters makes "U.S.A." the same term as "USA".
+///
+/// # Examples
+///
+/// ```
+/// use tf_idf::lexer::lexing::join_abbreviations;
+///
+/// assert_eq!(join_abbreviations("Made in the U.S.A., e.g. here."), "Made in the USA, eg here.");
+/// assert_eq!(join_abbreviations("The end.Next sentence"), "The end.Next sentence");
+/// assert_eq!(join_abbreviations("the U.S.Army"), "the US Army");
+/// ```
+pub fn join_abbreviations(input: &str) -> Cow<'_, str> {
+    abbreviation_regex().replace_all(input, |captures: &Captures| {
+        let abbreviation = &captures[0];
+        let joined = abbreviation.replace('.', "");
+        // A final period directly followed by a word still separates the two
+        let followed = captures
+            .get(0)
+            .is_some_and(|found| input[found.end()..].starts_with(char::is_alphabetic));
+
+        if followed && abbreviation.ends_with('.') {
+            joined + " "
+        } else {
+            joined
+        }
+    })
+}
+
+/// Represents the language of the documents, which selects how text is split into words.
+///
+/// # Variants
+///
+/// * `Default` - Words are delimited by spaces and punctuation, see `Apostrophes::split`. This
+///   is the default.
+/// * `Chinese` - Words aren't delimited, so the text is segmented with the dictionary of the
+///   `jieba-rs` crate, see `segment_chinese`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Language {
+    #[default]
+    Default,
+    Chinese,
+}
+
+impl FromStr for Language {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "default" => Ok(Language::Default),
+            "zh" => Ok(Language::Chinese),
+            _ => Err(format!("Unsupported language: {}", s)),
+        }
+    }
+}
+
+/// Represents a step of the normalization applied to every word after splitting.
+///
+/// Whether a step applies is set by its own field of the `Tokenizer`, the `Pipeline` only sets
+/// the order of the steps.
+///
+/// # Variants
+///
+/// * `Lowercase` - Words are lowercased. Always applied.
+/// * `FoldAccents` - Accented characters are folded to their base form with `fold_accents`, if
+///   `Tokenizer::fold_accents` is set.
+/// * `Stopwords` - The words in `Tokenizer::stopwords` are dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Step {
+    Lowercase,
+    FoldAccents,
+    Stopwords,
+}
+
+impl Step {
+    /// All the steps, in their default order.
+    pub const ALL: [Step; 3] = [Step::Lowercase, Step::FoldAccents, Step::Stopwords];
+
+    /// Applies the step to the words, with the settings of `tokenizer`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tf_idf::lexer::lexing::{Step, Tokenizer};
+    ///
+    /// let tokenizer = Tokenizer {
+    ///     fold_accents: true,
+    ///     ..Tokenizer::default()
+    /// };
+    /// let words = vec!["café".to_string(), "crème".to_string()];
+    ///
+    /// assert_eq!(Step::FoldAccents.apply(&tokenizer, words), vec!["cafe", "creme"]);
+    /// ```
+    pub fn apply(&self, tokenizer: &Tokenizer, words: Vec<String>) -> Vec<String> {
+        match self {
+            Step::Lowercase => words.iter().map(|word| word.to_lowercase()).collect(),
+            Step::FoldAccents if tokenizer.fold_accents => {
+                words.iter().map(|word| fold_accents(word)).collect()
+            }
+            Step::Stopwords if !tokenizer.stopwords.is_empty() => words
+                .into_iter()
+                .filter(|word| !tokenizer.stopwords.contains(word))
+                .collect(),
+            _ => words,
+        }
+    }
+}
+
+impl FromStr for Step {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lowercase" => Ok(Step::Lowercase),
+            "fold-accents" => Ok(Step::FoldAccents),
+            "stopwords" => Ok(Step::Stopwords),
+            _ => Err(format!("Unknown normalization step: {}", s)),
+        }
+    }
+}
+
+/// Represents the order the normalization steps of the `Tokenizer` are applied in.
+///
+/// The order matters when steps interact: stop words are compared to the words as they are at
+/// their step, so with the default order of folding accents first, the stop word "café" doesn't
+/// match anything, while with the stop words first it drops "café" before it is folded. Likewise,
+/// with the stop words before lowercasing, the stop word "us" drops "us" but keeps "US".
+///
+/// Every `Step` occurs exactly once, except `Step::Lowercase`, which comes first when it is left
+/// out, since words were always lowercased while splitting before it was a step. The default
+/// order is `Step::ALL`.
+///
+/// # Examples
+///
+/// ```
+/// use tf_idf::lexer::lexing::{Pipeline, Step};
+///
+/// let pipeline: Pipeline = "stopwords,lowercase,fold-accents".parse().unwrap();
+///
+/// assert_eq!(pipeline.steps(), &[Step::Stopwords, Step::Lowercase, Step::FoldAccents]);
+/// assert_eq!("fold-accents,stopwords".parse(), Ok(Pipeline::default()));
+/// assert!("stopwords".parse::<Pipeline>().is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "Vec<Step>")]
+pub struct Pipeline(Vec<Step>);
+
+impl Pipeline {
+    /// Returns the steps, in the order they are applied in.
+    pub fn steps(&self) -> &[Step] {
+        &self.0
+    }
+}
+
+impl Default for Pipeline {
+    fn default() -> Self {
+        Pipeline(Step::ALL.to_vec())
+    }
+}
+
+impl From<Vec<Step>> for Pipeline {
+    /// Creates a pipeline of the steps, putting `Step::Lowercase` first if it is missing, so
+    /// indexes built before it was a step keep their order.
+    fn from(mut steps: Vec<Step>) -> Self {
+        if !steps.contains(&Step::Lowercase) {
+            steps.insert(0, Step::Lowercase);
+        }
+
+        Pipeline(steps)
+    }
+}
+
+impl FromStr for Pipeline {
+    type Err = String;
+
+    /// Parses a comma-separated list of every step, such as `stopwords,lowercase,fold-accents`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Pipeline(steps) = s
+            .split(',')
+            .map(|step| step.trim().parse())
+            .collect::<Result<Vec<Step>, String>>()?
+            .into();
+
+        for step in Step::ALL {
+            let count = steps.iter().filter(|&&other| other == step).count();
+            if count != 1 {
+                return Err(format!(
+                    "Invalid pipeline: {}, expected every step exactly once",
+                    s
+                ));
+            }
+        }
+
+        Ok(Pipeline(steps))
+    }
+}
+
+/// Segments Chinese text into words with a dictionary.
+///
+/// The text is segmented with `jieba-rs`, which also keeps runs of Latin letters
+/// and digits as words. Segments without any letter or digit, such as punctuation and spaces, are
+/// dropped. The dictionary is loaded the first time this function is called.
+///
+/// # Examples
+///
+/// ```
+/// use tf_idf::lexer::lexing::segment_chinese;
+///
+/// assert_eq!(segment_chinese("我们学习Rust。"), vec!["我们", "学习", "Rust"]);
+/// ```
+pub fn segment_chinese(input: &str) -> Vec<String> {
+    static JIEBA: OnceLock<Jieba> = OnceLock::new();
+    JIEBA
+        .get_or_init(Jieba::new)
+        .cut(input, true)
+        .into_iter()
+        .map(|token| token.word)
+        .filter(|word| word.chars().any(char::is_alphanumeric))
+        .map(String::from)
+        .collect()
+}
+
+/// Represents the settings used to turn text into terms.
+///
+/// The same `Tokenizer` must be used for indexing and for querying, otherwise query terms won't
+/// match the indexed terms.
+///
+/// # Fields
+///
+/// * `fold_accents` - Whether to normalize accented characters to their base form, so "cafe"
+///   matches "café". This is wrong for some languages, so it is off by default.
+/// * `stopwords` - The words that are left out of the terms. Empty by default.
+/// * `apostrophes` - How apostrophes inside words are handled.
+/// * `links` - How URLs and email addresses are tokenized.
+/// * `language` - The language of the documents, which selects how text is split into words.
+///   `apostrophes` only applies to the default language.
+/// * `abbreviations` - Whether dotted abbreviations such as "U.S.A." and "e.g." are kept as
+///   single terms, see `join_abbreviations`. Only applies to the default language.
+/// * `decimals` - Whether decimal and version numbers such as "3.14" and "v2.0" are kept as
+///   single terms instead of being split on their periods. Only applies to the default language.
+/// * `pipeline` - The order the normalization steps are applied in after splitting, see
+///   `Pipeline`.
+/// * `max_token_len` - The length in characters above which words are dropped, if set. Binary
+///   data and mangled PDF text can produce "words" thousands of characters long, which would only
+///   bloat the index.
+///
+/// # Examples
+///
+/// ```
+/// use tf_idf::lexer::lexing::{Apostrophes, Language, Links, Pipeline, Stopwords, Tokenizer};
+///
+/// let tokenizer = Tokenizer {
+///     fold_accents: true,
+///     stopwords: Stopwords::new([String::from("the")]),
+///     apostrophes: Apostrophes::Split,
+///     links: Links::Split,
+///     language: Language::Default,
+///     abbreviations: false,
+///     decimals: false,
+///     pipeline: Pipeline::default(),
+///     max_token_len: None,
+/// };
+///
+/// assert_eq!(tokenizer.tokenize("The Café Crème"), vec!["cafe", "creme"]);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Tokenizer {
+    #[serde(default)]
+    pub fold_accents: bool,
+    #[serde(default)]
+    pub stopwords: Stopwords,
+    #[serde(default)]
+    pub apostrophes: Apostrophes,
+    #[serde(default)]
+    pub links: Links,
+    #[serde(default)]
+    pub language: Language,
+    #[serde(default)]
+    pub abbreviations: bool,
+    #[serde(default)]
+    pub decimals: bool,
+    #[serde(default)]
+    pub pipeline: Pipeline,
+    #[serde(default)]
+    pub max_token_len: Option<usize>,
+}
+
+impl Tokenizer {
+    /// Splits the input string into words according to the language: with `Apostrophes::split`
+    /// by default, or with `segment_chinese` for Chinese. Dotted abbreviations are joined first
+    /// if `abbreviations` is set, and decimal numbers are kept whole if `decimals` is set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tf_idf::lexer::lexing::Tokenizer;
+    ///
+    /// let tokenizer = Tokenizer {
+    ///     decimals: true,
+    ///     ..Tokenizer::default()
+    /// };
+    ///
+    /// assert_eq!(tokenizer.split("Pi is 3.14 since v2.0."), vec!["Pi", "is", "3.14", "since", "v2.0"]);
+    /// assert_eq!(tokenizer.split("Python 3.12!"), vec!["Python", "3.12", "!"]);
+    /// ```
+    pub fn split(&self, input: &str) -> Vec<String> {
+        let input = match self.language {
+            Language::Default if self.abbreviations => join_abbreviations(input),
+            Language::Default => Cow::Borrowed(input),
+            Language::Chinese => return segment_chinese(input),
+        };
+        if !self.decimals {
+            return self.apostrophes.split(&input);
+        }
+
+        let mut words: Vec<String> = Vec::new();
+        let mut last = 0;
+        for number in decimal_regex().find_iter(&input) {
+            words.extend(self.apostrophes.split(&input[last..number.start()]));
+            // Punctuation ending a sentence isn't part of the number
+            let text = number.as_str().trim_end_matches([';', ':', '!', '?']);
+            words.push(text.to_string());
+            last = number.start() + text.len();
+        }
+        words.extend(self.apostrophes.split(&input[last..]));
+
+        words
+    }
+
+    /// Splits the input string into terms with `split`, and normalizes them with `normalize`.
+    ///
+    /// URLs and email addresses are found with a regex before splitting, and kept as single
+    /// terms or removed according to `links`. Words longer than `max_token_len` are dropped right
+    /// after splitting, before they are normalized.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tf_idf::lexer::lexing::{Links, Tokenizer};
+    ///
+    /// let tokenizer = Tokenizer {
+    ///     links: Links::Keep,
+    ///     ..Tokenizer::default()
+    /// };
+    ///
+    /// assert_eq!(
+    ///     tokenizer.tokenize("Mail jane@example.com or see https://example.com/docs."),
+    ///     vec!["mail", "jane@example.com", "or", "see", "https://example.com/docs"]
+    /// );
+    /// ```
+    pub fn tokenize(&self, input: &str) -> Vec<String> {
+        let words = match self.links {
+            Links::Split => self.split(input),
+            Links::Strip => self.split(&link_regex().replace_all(input, " ")),
+            Links::Keep => {
+                let mut words: Vec<String> = Vec::new();
+                let mut last = 0;
+
+                for link in link_regex().find_iter(input) {
+                    words.extend(self.split(&input[last..link.start()]));
+                    // Punctuation ending a sentence isn't part of the link
+                    let text = link
+                        .as_str()
+                        .trim_end_matches(['.', ',', ';', ':', '!', '?']);
+                    words.push(text.to_string());
+                    last = link.start() + text.len();
+                }
+                words.extend(self.split(&input[last..]));
+
+                words
+            }
+        };
+        let words = match self.max_token_len {
+            Some(max) => words
+                .into_iter()
+                .filter(|word| word.chars().count() <= max)
+                .collect(),
+            None => words,
+        };
+
+        self.normalize(words)
+    }
+
+    /// Applies the normalization steps of the `pipeline` to the words, in order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tf_idf::lexer::lexing::{split_into_words, Stopwords, Tokenizer};
+    ///
+    /// let tokenizer = Tokenizer {
+    ///     fold_accents: true,
+    ///     stopwords: Stopwords::new([String::from("café")]),
+    ///     pipeline: "stopwords,fold-accents".parse().unwrap(),
+    ///     ..Tokenizer::default()
+    /// };
+    ///
+    /// assert_eq!(tokenizer.normalize(split_into_words("Café Crème")), vec!["creme"]);
+    /// ```
+    pub fn normalize(&self, words: Vec<String>) -> Vec<String> {
+        self.pipeline
+            .steps()
+            .iter()
+            .fold(words, |words, step| step.apply(self, words))
+    }
+}
+
+/// Folds accented characters to their base form.
+///
+/// The input is decomposed with Unicode NFKD and the combining marks are dropped.
+///
+/// # Examples
+///
+/// ```
+/// use tf_idf::lexer::lexing::fold_accents;
+///
+/// assert_eq!(fold_accents("naïve résumé"), "naive resume");
+/// ```
+pub fn fold_accents(input: &str) -> String {
+    input.nfkd().filter(|c| !is_combining_mark(*c)).collect()
+}
+
+/// Maps every term of a document to the pages it occurs on.
+///
+/// # Arguments
+///
+/// * `pages` - The text of every page, in order.
+/// * `tokenizer` - The `Tokenizer` used to split the pages into terms.
+///
+/// # Returns
+///
+/// A `TermPages` mapping every term to its sorted page numbers, starting at 1.
+///
+/// # Examples
+///
+/// ```
+/// use tf_idf::lexer::lexing::{index_pages, Tokenizer};
+///
+/// let pages = index_pages(["rust basics", "more rust"].into_iter(), &Tokenizer::default());
+///
+/// assert_eq!(pages["rust"], vec![1, 2]);
+/// assert_eq!(pages["more"], vec![2]);
+/// ```
+pub fn index_pages<'a>(pages: impl Iterator<Item = &'a str>, tokenizer: &Tokenizer) -> TermPages {
+    let mut term_pages = TermPages::new();
+
+    for (idx, page) in pages.enumerate() {
+        for term in tokenizer.tokenize(page) {
+            let numbers = term_pages.entry(term).or_default();
+            if numbers.last() != Some(&(idx + 1)) {
+                numbers.push(idx + 1);
+            }
+        }
+    }
+
+    term_pages
+}
+
+/// Splits a file path into searchable terms.
+///
+/// This function tokenizes the directory names and the file stem of `path` with the given
+/// `Tokenizer`. Underscores and dashes, which are common word separators in file names,
+/// are treated as delimiters too. The file extension is left out.
+///
+/// # Arguments
+///
+/// * `path` - The path of the document.
+/// * `tokenizer` - The `Tokenizer` used to split the path into terms.
+///
+/// # Returns
+///
+/// A vector of strings containing the terms of the path.
+///
+/// # Examples
+///
+/// ```
+/// use std::path::Path;
+/// use tf_idf::lexer::lexing::{path_terms, Tokenizer};
+///
+/// let terms = path_terms(Path::new("books/rust_in_action.pdf"), &Tokenizer::default());
+///
+/// assert_eq!(terms, vec!["books", "rust", "in", "action"]);
+/// ```
+pub fn path_terms(path: &Path, tokenizer: &Tokenizer) -> Vec<String> {
+    let directories = path
+        .parent()
+        .into_iter()
+        .flat_map(Path::components)
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name),
+            _ => None,
+        });
+    let names = directories.chain(path.file_stem());
+
+    names
+        .flat_map(|name| tokenizer.tokenize(&name.to_string_lossy().replace(['_', '-'], " ")))
+        .collect()
+}
@@ -1,17 +1,43 @@
-use super::lexing::Document;
+use super::index::{Index, IndexSettings};
+use super::lexing::{index_data, DocFreq, DocFreqExt, Document, TermFreq};
+use super::scoring::{document_frequencies, vocabulary, IdfVariant};
+use flate2::read::GzDecoder;
+use log::warn;
+use memmap2::Mmap;
 use poppler::PopplerDocument;
-use std::{fs, io};
-use std::{fs::read_dir, path::PathBuf};
+use regex::Regex;
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::io::{BufWriter, Read, Write};
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::time::SystemTime;
+use std::{
+    fs::read_dir,
+    path::{Path, PathBuf},
+};
+use std::{
+    fs::{self, File},
+    io,
+};
+use unicode_normalization::UnicodeNormalization;
 
 /// Searches for files with a specific filetype in a directory.
 ///
-/// This function takes a directory path represented as a `String` and a filetype as a `&str`,
+/// This function takes a directory path as a `&Path` and a filetype as a `&str`,
 /// and returns a `Result` containing a `Vec<PathBuf>` with the paths of the matching files found in the directory.
+/// The filetype may list several extensions separated by commas, such as `pdf,txt,md`, and each
+/// extension may be written as a glob, such as `*.pdf`. See `parse_filetypes`. Gzip-compressed
+/// files match the extension of the compressed file, so `notes.txt.gz` matches `txt`. Symbolic
+/// links are handled according to `symlinks`. Hidden files, whose name starts with a `.`, such as
+/// the `.data.json` index itself or editor swap files, are skipped unless `include_hidden` is set.
 ///
 /// # Arguments
 ///
-/// * `path` - A `String` representing the directory path to search in.
-/// * `filetype` - A `&str` representing the desired filetype to search for.
+/// * `path` - A `&Path` representing the directory path to search in.
+/// * `filetype` - A `&str` representing the desired filetypes to search for.
+/// * `symlinks` - How symbolic links in the directory are handled.
+/// * `include_hidden` - Whether hidden files are searched too.
 ///
 /// # Returns
 ///
@@ -20,13 +46,14 @@ use std::{fs::read_dir, path::PathBuf};
 ///
 /// # Examples
 ///
-/// ```
-/// use std::path::PathBuf;
+/// ```no_run
+/// use std::path::Path;
+/// use tf_idf::lexer::lib::{search_filetype, Symlinks};
 ///
-/// let path = String::from("/path/to/directory");
-/// let filetype = "txt";
+/// let path = Path::new("/path/to/directory");
+/// let filetype = "pdf,txt";
 ///
-/// match search_filetype(&path, filetype) {
+/// match search_filetype(path, filetype, Symlinks::Skip, false) {
 ///     Ok(files) => {
 ///         for file in files {
 ///             println!("{}", file.display());
@@ -37,14 +64,35 @@ use std::{fs::read_dir, path::PathBuf};
 ///     }
 /// }
 /// ```
-pub fn search_filetype(path: &String, filetype: &str) -> std::io::Result<Vec<PathBuf>> {
+pub fn search_filetype(
+    path: &Path,
+    filetype: &str,
+    symlinks: Symlinks,
+    include_hidden: bool,
+) -> std::io::Result<Vec<PathBuf>> {
     let mut files_vec: Vec<PathBuf> = Vec::new();
+    let filetypes = parse_filetypes(filetype);
     let files = read_dir(path)?;
 
     for fp in files {
-        let path = fp?.path();
-        if let Some(extension) = path.extension() {
-            if extension == filetype {
+        let entry = fp?;
+        if !include_hidden && entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let mut path = entry.path();
+        if entry.file_type()?.is_symlink() {
+            // A broken link has no target to index
+            let Ok(target) = fs::canonicalize(&path) else {
+                continue;
+            };
+            match symlinks {
+                Symlinks::Skip => continue,
+                Symlinks::Follow => path = target,
+                Symlinks::Link => {}
+            }
+        }
+        if let Some(extension) = document_extension(&path) {
+            if filetypes.contains(&extension) {
                 files_vec.push(path)
             }
         }
@@ -53,56 +101,1136 @@ pub fn search_filetype(path: &String, filetype: &str) -> std::io::Result<Vec<Pat
     Ok(files_vec)
 }
 
-/// This function takes a `PathBuf` argument representing the path to a PDF document and returns a `String`
-/// containing the concatenated text content of all pages in the PDF document.
+/// Represents how symbolic links are handled when searching a directory for documents.
+///
+/// # Variants
+///
+/// * `Skip` - Symbolic links are ignored, so nothing outside the directory is indexed and no
+///   file is indexed twice through a link. This is the default.
+/// * `Follow` - The target of a link is indexed under its own, resolved path.
+/// * `Link` - The target of a link is indexed under the path of the link.
+///
+/// Broken links are always ignored.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Symlinks {
+    #[default]
+    Skip,
+    Follow,
+    Link,
+}
+
+impl FromStr for Symlinks {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(Symlinks::Skip),
+            "follow" => Ok(Symlinks::Follow),
+            "link" => Ok(Symlinks::Link),
+            _ => Err(format!("Unknown symlink handling: {}", s)),
+        }
+    }
+}
+
+/// Returns the lowercase extension of the type of a document.
+///
+/// For gzip-compressed files ending in `.gz`, this is the extension of the compressed file.
+///
+/// # Examples
+///
+/// ```
+/// use std::path::Path;
+/// use tf_idf::lexer::lib::document_extension;
+///
+/// assert_eq!(document_extension(Path::new("book.PDF")), Some("pdf".to_string()));
+/// assert_eq!(document_extension(Path::new("notes.txt.gz")), Some("txt".to_string()));
+/// assert_eq!(document_extension(Path::new("README")), None);
+/// ```
+pub fn document_extension(path: &Path) -> Option<String> {
+    let path = if is_gzip(path) {
+        Path::new(path.file_stem()?)
+    } else {
+        path
+    };
+
+    path.extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+}
+
+/// Returns whether the file is gzip-compressed, judging by its `.gz` extension.
+fn is_gzip(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("gz"))
+}
+
+/// Decompresses gzip-compressed data.
+fn gunzip(compressed: &[u8]) -> io::Result<Vec<u8>> {
+    let mut data: Vec<u8> = Vec::new();
+    GzDecoder::new(compressed).read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// Returns whether the path is an `http://` or `https://` URL rather than a local file.
+///
+/// # Examples
+///
+/// ```
+/// use std::path::Path;
+/// use tf_idf::lexer::lib::is_url;
+///
+/// assert!(is_url(Path::new("https://example.com/paper.pdf")));
+/// assert!(!is_url(Path::new("books/paper.pdf")));
+/// ```
+pub fn is_url(path: &Path) -> bool {
+    path.to_str()
+        .is_some_and(|path| path.starts_with("http://") || path.starts_with("https://"))
+}
+
+/// Downloads the document at a URL.
+///
+/// Responses with an error status are returned as errors, so a document that can't be downloaded
+/// is skipped like any other unreadable document.
+#[cfg(feature = "http")]
+fn download(url: &Path) -> io::Result<Vec<u8>> {
+    let response = ureq::get(&url.to_string_lossy())
+        .call()
+        .map_err(io::Error::other)?;
+
+    let mut data: Vec<u8> = Vec::new();
+    response.into_reader().read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// Downloading needs the `http` feature, without it URLs can't be read.
+#[cfg(not(feature = "http"))]
+fn download(url: &Path) -> io::Result<Vec<u8>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!(
+            "{:?} is a URL, but tf-idf was built without the http feature",
+            url
+        ),
+    ))
+}
+
+/// Reads a list of files to index from a manifest.
+///
+/// The manifest holds one path per line, such as the output of `find`. Relative paths are relative
+/// to the current directory, not to the manifest. Blank lines are ignored.
 ///
 /// # Arguments
 ///
-/// * `doc` - A `PathBuf` representing the path to the PDF document.
+/// * `manifest` - A `&Path` representing the path of the manifest.
 ///
 /// # Returns
 ///
-/// A `String` containing the concatenated text content of all pages in the PDF document.
+/// An `io::Result` containing the listed paths, in order. The paths aren't checked for existence.
 ///
-/// # Panics
+/// # Errors
 ///
-/// This function will panic if it encounters any errors while reading the document.
+/// This function returns an `io::Error` if the manifest can't be read.
 ///
 /// # Examples
 ///
+/// ```no_run
+/// use std::path::Path;
+/// use tf_idf::lexer::lib::read_file_list;
+///
+/// let paths = read_file_list(Path::new("corpus.txt")).expect("Couldn't read the manifest");
 /// ```
+pub fn read_file_list(manifest: &Path) -> io::Result<Vec<PathBuf>> {
+    let content = fs::read_to_string(manifest)?;
+
+    Ok(content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(PathBuf::from)
+        .collect())
+}
+
+/// Reads the boosts of documents from a side file.
+///
+/// Every line holds a multiplier followed by the path of a document, separated by whitespace,
+/// such as `2.5 papers/reference.pdf`. The rest of the line after the multiplier is the path, so
+/// paths may contain spaces. The paths have to be written as they are stored in the index, as
+/// listed in the results. Blank lines and lines starting with `#` are ignored.
+///
+/// # Arguments
+///
+/// * `path` - A `&Path` representing the path of the side file.
+///
+/// # Returns
+///
+/// An `io::Result` containing the multiplier of every listed document.
+///
+/// # Errors
+///
+/// This function returns an `io::Error` if the file can't be read, and an error of kind
+/// `io::ErrorKind::InvalidData` if a line doesn't start with a multiplier of at least zero or has
+/// no path.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::path::Path;
+/// use tf_idf::lexer::lib::read_boosts;
+///
+/// let boosts = read_boosts(Path::new("boosts.txt")).expect("Couldn't read the boosts");
+/// ```
+pub fn read_boosts(path: &Path) -> io::Result<HashMap<PathBuf, f32>> {
+    let content = fs::read_to_string(path)?;
+    let mut boosts: HashMap<PathBuf, f32> = HashMap::new();
+
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let invalid = || {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Invalid boost on line {} of {:?}, expected a multiplier and a path",
+                    number + 1,
+                    path
+                ),
+            )
+        };
+        let (boost, document) = line.split_once(char::is_whitespace).ok_or_else(invalid)?;
+        let boost: f32 = boost.parse().map_err(|_| invalid())?;
+        if !boost.is_finite() || boost < 0.0 {
+            return Err(invalid());
+        }
+        boosts.insert(PathBuf::from(document.trim()), boost);
+    }
+
+    Ok(boosts)
+}
+
+/// Parses a filetype argument into a set of lowercase extensions.
+///
+/// The argument is split on commas, and a leading `*.` or `.` is stripped from every extension.
+///
+/// # Examples
+///
+/// ```
+/// use tf_idf::lexer::lib::parse_filetypes;
+///
+/// let filetypes = parse_filetypes("pdf, *.TXT,.md");
+///
+/// assert!(filetypes.contains("pdf"));
+/// assert!(filetypes.contains("txt"));
+/// assert!(filetypes.contains("md"));
+/// ```
+pub fn parse_filetypes(filetype: &str) -> HashSet<String> {
+    filetype
+        .split(',')
+        .map(|extension| {
+            let extension = extension.trim();
+            let extension = extension.strip_prefix('*').unwrap_or(extension);
+            let extension = extension.strip_prefix('.').unwrap_or(extension);
+            extension.to_lowercase()
+        })
+        .filter(|extension| !extension.is_empty())
+        .collect()
+}
+
+/// Represents the options controlling how the text of a document is read.
+///
+/// # Fields
+///
+/// * `dehyphenate` - Whether to rejoin words hyphenated across line breaks.
+/// * `password` - The password used to open encrypted PDF documents. Empty for no password.
+/// * `strip_invalid` - Whether to remove the replacement characters (U+FFFD) standing in for
+///   text that wasn't valid UTF-8, which would otherwise end up in junk terms.
+/// * `ligatures` - How ligature characters such as "ﬁ" are handled.
+#[derive(Debug, Clone, Default)]
+pub struct ReadOptions {
+    pub dehyphenate: bool,
+    pub password: String,
+    pub strip_invalid: bool,
+    pub ligatures: Ligatures,
+}
+
+/// Represents how ligature characters in extracted text are handled.
+///
+/// PDF documents often encode ligatures such as "ﬁ" and "ﬂ" as single code points, so "ﬁnd" in a
+/// document wouldn't match a query for "find".
+///
+/// # Variants
+///
+/// * `Expand` - Expand the Latin ligatures (U+FB00 to U+FB06) to their component letters.
+/// * `Nfkc` - Normalize the whole text with Unicode NFKC, which expands the ligatures and also
+///   folds other compatibility characters, such as fullwidth letters and superscript digits.
+/// * `Keep` - Keep the ligatures as they are.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Ligatures {
+    #[default]
+    Expand,
+    Nfkc,
+    Keep,
+}
+
+impl Ligatures {
+    /// Applies the ligature handling to `text`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tf_idf::lexer::lib::Ligatures;
+    ///
+    /// assert_eq!(Ligatures::Expand.apply("\u{FB01}nd the \u{FB02}ow"), "find the flow");
+    /// assert_eq!(Ligatures::Nfkc.apply("\u{FB00}ect x\u{00B2}"), "ffect x2");
+    /// assert_eq!(Ligatures::Keep.apply("\u{FB01}nd"), "\u{FB01}nd");
+    /// ```
+    pub fn apply(&self, text: &str) -> String {
+        match self {
+            Ligatures::Expand => text
+                .chars()
+                .flat_map(|c| match c {
+                    '\u{FB00}'..='\u{FB06}' => c.to_string().nfkc().collect::<Vec<char>>(),
+                    c => vec![c],
+                })
+                .collect(),
+            Ligatures::Nfkc => text.nfkc().collect(),
+            Ligatures::Keep => text.to_string(),
+        }
+    }
+}
+
+impl FromStr for Ligatures {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "expand" => Ok(Ligatures::Expand),
+            "nfkc" => Ok(Ligatures::Nfkc),
+            "keep" => Ok(Ligatures::Keep),
+            _ => Err(format!("Unknown ligature handling: {}", s)),
+        }
+    }
+}
+
+/// Represents the text read from a document.
+///
+/// # Fields
+///
+/// * `content` - The normalized text content of the document.
+/// * `title` - The title of the document taken from its metadata, if any.
+/// * `page_starts` - The byte offset in `content` where every page starts. Empty for documents
+///   without pages, such as plain text files.
+/// * `invalid_chars` - The number of replacement characters (U+FFFD) found in the text, whether
+///   they were stripped or not.
+/// * `fields` - The other metadata fields of the document, such as the author, as pairs of a
+///   lowercase field name and a value.
+#[derive(Debug, Clone, Default)]
+pub struct DocumentText {
+    pub content: String,
+    pub title: Option<String>,
+    pub page_starts: Vec<usize>,
+    pub invalid_chars: usize,
+    pub fields: Vec<(String, String)>,
+}
+
+impl DocumentText {
+    /// Returns the text of every page, in order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tf_idf::lexer::lib::DocumentText;
+    ///
+    /// let text = DocumentText {
+    ///     content: "first page second page".to_string(),
+    ///     title: None,
+    ///     page_starts: vec![0, 11],
+    ///     invalid_chars: 0,
+    ///     fields: Vec::new(),
+    /// };
+    ///
+    /// assert_eq!(text.pages().collect::<Vec<_>>(), vec!["first page ", "second page"]);
+    /// ```
+    pub fn pages(&self) -> impl Iterator<Item = &str> {
+        let ends = self.page_starts.iter().skip(1).copied();
+
+        self.page_starts
+            .iter()
+            .zip(ends.chain([self.content.len()]))
+            .map(|(&start, end)| &self.content[start..end])
+    }
+}
+
+/// Reads the text content and the title of a PDF document.
+///
+/// The text content is the text content of all pages in the PDF document, every page normalized
+/// with `normalize_text` and separated by a space. The offset where every page starts is kept, so
+/// terms can be traced back to their pages. The title is taken from the document metadata and is
+/// `None` if the document has no title.
+///
+/// # Arguments
+///
+/// * `doc` - A `PathBuf` representing the path to the PDF document.
+/// * `options` - The `ReadOptions` controlling how the text is read.
+///
+/// # Returns
+///
+/// The `DocumentText` of the PDF document.
+///
+/// # Errors
+///
+/// This function returns an `io::Error` if the document can't be opened, for example because it
+/// is not a valid PDF or the password is wrong.
+///
+/// # Examples
+///
+/// ```no_run
 /// use std::path::PathBuf;
+/// use tf_idf::lexer::lib::{read_pdf, ReadOptions};
 ///
 /// let doc = PathBuf::from("path/to/my/document.pdf");
-/// let content = read_from_pdf(&doc);
-/// println!("{}", content);
+/// let text = read_pdf(&doc, &ReadOptions::default()).expect("Couldn't read the document");
+/// println!("{:?}: {} pages", text.title, text.page_starts.len());
 /// ```
-pub fn read_from_pdf(doc: &PathBuf) -> String {
-    let pdf = PopplerDocument::new_from_file(doc, "").expect("Coulnd't read the document");
+pub fn read_pdf(doc: &PathBuf, options: &ReadOptions) -> io::Result<DocumentText> {
+    let pdf = PopplerDocument::new_from_file(doc, &options.password)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error.to_string()))?;
+
+    Ok(pdf_text(&pdf, options))
+}
+
+/// Reads the text content and the title of a PDF document held in memory, like `read_pdf`.
+fn read_pdf_data(mut data: Vec<u8>, options: &ReadOptions) -> io::Result<DocumentText> {
+    // The document refers to the data without a lifetime, so the data must outlive it, which it
+    // does as an argument of this function
+    let pdf = PopplerDocument::new_from_data(&mut data, &options.password)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error.to_string()))?;
+
+    Ok(pdf_text(&pdf, options))
+}
+
+/// Extracts the text content, the page offsets and the title of an opened PDF document.
+fn pdf_text(pdf: &PopplerDocument, options: &ReadOptions) -> DocumentText {
     let mut buff = String::new();
+    let mut page_starts: Vec<usize> = Vec::new();
+    let mut invalid_chars = 0;
     let num_of_pgs = pdf.get_n_pages();
 
     for page_num in 0..num_of_pgs {
-        if let Some(page) = pdf.get_page(page_num) {
-            match page.get_text() {
-                Some(content) => buff.push_str(content),
-                None => continue,
+        // Pages without text still count, so the page numbers stay right
+        let page = pdf.get_page(page_num);
+        let (content, invalid) = page
+            .as_ref()
+            .and_then(|page| page.get_text())
+            .map(|content| clean_text(content, options))
+            .unwrap_or_default();
+        invalid_chars += invalid;
+
+        // Pages are separated, so the last word of a page isn't glued to the first of the next
+        if !buff.is_empty() && !buff.ends_with(' ') {
+            buff.push(' ');
+        }
+        page_starts.push(buff.len());
+        buff.push_str(&content);
+    }
+
+    let title = pdf
+        .get_title()
+        .map(|title| {
+            normalize_text(&options.ligatures.apply(&title))
+                .trim()
+                .to_string()
+        })
+        .filter(|title| !title.is_empty());
+    let fields = pdf
+        .get_metadata()
+        .map(|xmp| xmp_fields(&xmp))
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(name, value)| (name, normalize_text(&options.ligatures.apply(&value))))
+        .collect();
+
+    DocumentText {
+        content: buff,
+        title,
+        page_starts,
+        invalid_chars,
+        fields,
+    }
+}
+
+/// Reads the metadata fields of a PDF document from its XMP metadata.
+///
+/// The `author` field is taken from `dc:creator`, the `keywords` field from `pdf:Keywords`, or
+/// else `dc:subject`, and the `subject` field from `dc:description`. Lists, such as several
+/// authors, are joined with commas. Missing and empty fields are left out.
+///
+/// # Arguments
+///
+/// * `xmp` - The XMP metadata, an XML document.
+///
+/// # Returns
+///
+/// The fields found, as pairs of a field name and a value.
+///
+/// # Examples
+///
+/// ```
+/// use tf_idf::lexer::lib::xmp_fields;
+///
+/// let xmp = "<dc:creator><rdf:Seq><rdf:li>Jane Smith</rdf:li></rdf:Seq></dc:creator>";
+///
+/// assert_eq!(xmp_fields(xmp), vec![("author".to_string(), "Jane Smith".to_string())]);
+/// ```
+pub fn xmp_fields(xmp: &str) -> Vec<(String, String)> {
+    let keywords = xmp_value(xmp, "pdf:Keywords").or_else(|| xmp_value(xmp, "dc:subject"));
+
+    [
+        ("author", xmp_value(xmp, "dc:creator")),
+        ("keywords", keywords),
+        ("subject", xmp_value(xmp, "dc:description")),
+    ]
+    .into_iter()
+    .filter_map(|(name, value)| Some((name.to_string(), value?)))
+    .collect()
+}
+
+/// Returns the text of an XMP element or attribute, with its list items joined by commas.
+fn xmp_value(xmp: &str, tag: &str) -> Option<String> {
+    static ITEM: OnceLock<Regex> = OnceLock::new();
+    let item = ITEM.get_or_init(|| {
+        Regex::new(r"(?s)<rdf:li[^>]*>(.*?)</rdf:li>").expect("Invalid XMP item regex")
+    });
+
+    let tag = regex::escape(tag);
+    let element = Regex::new(&format!(r"(?s)<{tag}[^>]*>(.*?)</{tag}>")).ok()?;
+    let attribute = Regex::new(&format!(r#"{tag}="([^"]*)""#)).ok()?;
+    let inner = element
+        .captures(xmp)
+        .or_else(|| attribute.captures(xmp))?
+        .get(1)?
+        .as_str();
+
+    let value = if inner.contains("<rdf:li") {
+        item.captures_iter(inner)
+            .map(|captures| captures[1].trim().to_string())
+            .collect::<Vec<String>>()
+            .join(", ")
+    } else {
+        inner.trim().to_string()
+    };
+    let value = value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&");
+
+    Some(value).filter(|value| !value.is_empty())
+}
+
+/// Splits a front matter block off the start of a plain text document.
+///
+/// Markdown and other plain text documents often start with metadata between two `---` lines,
+/// one `name: value` field per line. Field names are lowercased, and quotes around values are
+/// removed. Lines that aren't fields are ignored.
+///
+/// # Arguments
+///
+/// * `text` - The text of the document.
+///
+/// # Returns
+///
+/// The fields, as pairs of a field name and a value, and the rest of the text. Text without a
+/// front matter block has no fields and is returned whole.
+///
+/// # Examples
+///
+/// ```
+/// use tf_idf::lexer::lib::front_matter;
+///
+/// let (fields, body) = front_matter("---\nAuthor: Jane Smith\n---\nThe text");
+///
+/// assert_eq!(fields, vec![("author".to_string(), "Jane Smith".to_string())]);
+/// assert_eq!(body, "The text");
+/// ```
+pub fn front_matter(text: &str) -> (Vec<(String, String)>, &str) {
+    let Some(rest) = text
+        .strip_prefix("---\n")
+        .or_else(|| text.strip_prefix("---\r\n"))
+    else {
+        return (Vec::new(), text);
+    };
+    let Some(end) = rest.find("\n---") else {
+        return (Vec::new(), text);
+    };
+
+    let fields = rest[..end]
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| {
+            let value = value.trim();
+            let value = value
+                .strip_prefix('"')
+                .and_then(|value| value.strip_suffix('"'))
+                .unwrap_or(value);
+            (name.trim().to_lowercase(), value.to_string())
+        })
+        .filter(|(name, value)| {
+            !name.is_empty()
+                && !value.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+        })
+        .collect();
+    let body = rest[end + 4..]
+        .split_once('\n')
+        .map_or("", |(_, body)| body);
+
+    (fields, body)
+}
+
+/// Cleans up extracted text according to the options.
+///
+/// Ligatures are handled according to `options.ligatures` first. The text is then dehyphenated
+/// if requested, normalized with `normalize_text`, and stripped of replacement characters
+/// (U+FFFD) if requested.
+///
+/// # Returns
+///
+/// The cleaned text and the number of replacement characters found in it.
+fn clean_text(text: &str, options: &ReadOptions) -> (String, usize) {
+    let text = options.ligatures.apply(text);
+    let mut text = if options.dehyphenate {
+        normalize_text(&dehyphenate(&text))
+    } else {
+        normalize_text(&text)
+    };
+
+    let invalid = text.matches('\u{FFFD}').count();
+    if options.strip_invalid && invalid > 0 {
+        text = text.replace('\u{FFFD}', "");
+    }
+
+    (text, invalid)
+}
+
+/// Destinations whose text is not part of the body of an RTF document, such as the font table.
+const RTF_SKIPPED_DESTINATIONS: &[&str] = &[
+    "fonttbl",
+    "colortbl",
+    "stylesheet",
+    "listtable",
+    "listoverridetable",
+    "revtbl",
+    "rsidtbl",
+    "filetbl",
+    "generator",
+    "pict",
+    "object",
+    "fldinst",
+    "header",
+    "headerl",
+    "headerr",
+    "headerf",
+    "footer",
+    "footerl",
+    "footerr",
+    "footerf",
+    "themedata",
+    "colorschememapping",
+    "latentstyles",
+    "datastore",
+    "xmlnstbl",
+];
+
+/// Fields of the `\info` group of an RTF document that are read as document fields.
+const RTF_INFO_FIELDS: &[&str] = &["title", "author", "subject", "keywords"];
+
+/// Where the text of the current RTF group goes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum RtfTarget {
+    Body,
+    Skip,
+    Field(String),
+}
+
+/// Decodes a byte of an RTF document, which uses the Windows-1252 code page by default. The
+/// five bytes Windows-1252 leaves undefined are decoded as the C1 control characters.
+fn cp1252(byte: u8) -> char {
+    match byte {
+        0x80 => '€',
+        0x82 => '‚',
+        0x83 => 'ƒ',
+        0x84 => '„',
+        0x85 => '…',
+        0x86 => '†',
+        0x87 => '‡',
+        0x88 => 'ˆ',
+        0x89 => '‰',
+        0x8a => 'Š',
+        0x8b => '‹',
+        0x8c => 'Œ',
+        0x8e => 'Ž',
+        0x91 => '‘',
+        0x92 => '’',
+        0x93 => '“',
+        0x94 => '”',
+        0x95 => '•',
+        0x96 => '–',
+        0x97 => '—',
+        0x98 => '˜',
+        0x99 => '™',
+        0x9a => 'š',
+        0x9b => '›',
+        0x9c => 'œ',
+        0x9e => 'ž',
+        0x9f => 'Ÿ',
+        _ => char::from(byte),
+    }
+}
+
+/// Extracts the plain text of an RTF document.
+///
+/// Control words and the groups that don't hold body text, such as the font table, the stylesheet,
+/// pictures and the page headers, are stripped, and so is the binary data of `\binN`. Paragraph and
+/// line breaks become newlines, `\'hh` escapes are decoded as Windows-1252 and `\uN` escapes as
+/// Unicode, skipping their fallback characters. The title, author, subject and keywords of the
+/// `\info` group are returned as fields.
+///
+/// # Arguments
+///
+/// * `data` - The raw bytes of the RTF document.
+///
+/// # Returns
+///
+/// The text of the document and its fields, as pairs of a field name and a value.
+///
+/// # Errors
+///
+/// This function returns an `io::Error` of kind `InvalidData` if the data doesn't start with an
+/// `{\rtf` group or its braces are unbalanced.
+///
+/// # Examples
+///
+/// ```
+/// use tf_idf::lexer::lib::rtf_to_text;
+///
+/// let (text, fields) = rtf_to_text(br"{\rtf1\ansi{\info{\title Notes}}Hello {\b bold}\par caf\'e9}")
+///     .expect("Couldn't read the document");
+/// assert_eq!(text, "Hello bold\ncafé");
+/// assert_eq!(fields, vec![("title".to_string(), "Notes".to_string())]);
+/// ```
+pub fn rtf_to_text(data: &[u8]) -> io::Result<(String, Vec<(String, String)>)> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    let data = data.trim_ascii_start();
+    if !data.starts_with(br"{\rtf") {
+        return Err(invalid("Not an RTF document"));
+    }
+
+    let mut text = String::new();
+    let mut fields: Vec<(String, String)> = Vec::new();
+    // The target and the number of fallback characters after `\uN` of every open group
+    let mut groups: Vec<(RtfTarget, usize)> = Vec::new();
+    let mut target = RtfTarget::Body;
+    let mut unicode_skip = 1;
+    let mut pending_skip = 0;
+    let mut closed = false;
+    let mut i = 0;
+
+    while i < data.len() {
+        if closed {
+            // Anything after the document group, usually a trailing newline or NUL, is ignored
+            break;
+        }
+        let mut output = None;
+        match data[i] {
+            b'{' => {
+                groups.push((target.clone(), unicode_skip));
+                pending_skip = 0;
+                i += 1;
+            }
+            b'}' => {
+                (target, unicode_skip) =
+                    groups.pop().ok_or_else(|| invalid("Unbalanced braces"))?;
+                closed = groups.is_empty();
+                pending_skip = 0;
+                i += 1;
+            }
+            b'\r' | b'\n' => i += 1,
+            b'\\' => {
+                let Some(&next) = data.get(i + 1) else {
+                    return Err(invalid("Control word cut off"));
+                };
+                if next.is_ascii_alphabetic() {
+                    let start = i + 1;
+                    let mut end = start;
+                    while end < data.len() && data[end].is_ascii_alphabetic() {
+                        end += 1;
+                    }
+                    let word = std::str::from_utf8(&data[start..end]).unwrap_or_default();
+                    let parameter_start = end;
+                    if end < data.len() && data[end] == b'-' {
+                        end += 1;
+                    }
+                    while end < data.len() && data[end].is_ascii_digit() {
+                        end += 1;
+                    }
+                    let parameter = std::str::from_utf8(&data[parameter_start..end])
+                        .ok()
+                        .and_then(|parameter| parameter.parse::<i32>().ok());
+                    // A space after a control word only delimits it
+                    if end < data.len() && data[end] == b' ' {
+                        end += 1;
+                    }
+                    i = end;
+
+                    match word {
+                        "par" | "line" | "sect" | "page" | "row" => output = Some('\n'),
+                        "tab" | "cell" => output = Some('\t'),
+                        "emdash" => output = Some('—'),
+                        "endash" => output = Some('–'),
+                        "bullet" => output = Some('•'),
+                        "lquote" => output = Some('‘'),
+                        "rquote" => output = Some('’'),
+                        "ldblquote" => output = Some('“'),
+                        "rdblquote" => output = Some('”'),
+                        "uc" => unicode_skip = parameter.unwrap_or(1).max(0) as usize,
+                        // Binary data, such as an embedded picture, whose bytes aren't RTF
+                        "bin" => {
+                            let len = parameter.unwrap_or(0).max(0) as usize;
+                            i = i.saturating_add(len).min(data.len());
+                        }
+                        "u" => {
+                            // Negative values are code units above 32767 written as signed
+                            let code = parameter.unwrap_or(0).rem_euclid(65536) as u32;
+                            if target != RtfTarget::Skip {
+                                let c = char::from_u32(code).unwrap_or('\u{FFFD}');
+                                push_rtf_char(&mut text, &mut fields, &target, c);
+                            }
+                            pending_skip = unicode_skip;
+                            continue;
+                        }
+                        "info" => target = RtfTarget::Skip,
+                        word if RTF_INFO_FIELDS.contains(&word) => {
+                            target = RtfTarget::Field(word.to_string())
+                        }
+                        word if RTF_SKIPPED_DESTINATIONS.contains(&word) => {
+                            target = RtfTarget::Skip
+                        }
+                        _ => {}
+                    }
+                } else {
+                    i += 2;
+                    match next {
+                        b'\'' => {
+                            let byte = data
+                                .get(i..i + 2)
+                                .and_then(|hex| std::str::from_utf8(hex).ok())
+                                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                                .ok_or_else(|| invalid("Invalid hexadecimal escape"))?;
+                            i += 2;
+                            output = Some(cp1252(byte));
+                        }
+                        // An ignorable destination, which readers that don't know it skip
+                        b'*' => target = RtfTarget::Skip,
+                        b'\\' | b'{' | b'}' => output = Some(char::from(next)),
+                        b'~' => output = Some('\u{00A0}'),
+                        b'_' => output = Some('-'),
+                        b'\r' | b'\n' => output = Some('\n'),
+                        _ => {}
+                    }
+                }
+            }
+            byte => {
+                output = Some(cp1252(byte));
+                i += 1;
+            }
+        }
+
+        if let Some(c) = output {
+            if pending_skip > 0 {
+                pending_skip -= 1;
+            } else if target != RtfTarget::Skip {
+                push_rtf_char(&mut text, &mut fields, &target, c);
             }
         }
     }
 
-    buff
+    if !closed {
+        return Err(invalid("Unbalanced braces"));
+    }
+    for (_, value) in fields.iter_mut() {
+        *value = value.trim().to_string();
+    }
+    Ok((text.trim().to_string(), fields))
+}
+
+/// Appends a character of an RTF document to the body text or to the field it belongs to.
+fn push_rtf_char(
+    text: &mut String,
+    fields: &mut Vec<(String, String)>,
+    target: &RtfTarget,
+    c: char,
+) {
+    match target {
+        RtfTarget::Body => text.push(c),
+        RtfTarget::Field(name) => match fields.iter_mut().find(|(field, _)| field == name) {
+            Some((_, value)) => value.push(c),
+            None => fields.push((name.clone(), c.to_string())),
+        },
+        RtfTarget::Skip => {}
+    }
+}
+
+/// Reads the text content and the title of a document of any supported format.
+///
+/// The reader is chosen by the extension of the file: PDF documents are read with `read_pdf`, RTF
+/// documents are converted to plain text with `rtf_to_text`, and every other file is read as UTF-8
+/// plain text, normalized with `normalize_text`. Plain text files have no pages, and their title
+/// and other fields are read from a front matter block, see `front_matter`. The fields of PDF
+/// documents are read from their metadata, see `xmp_fields`. Files ending in `.gz` are decompressed
+/// first, and read according to the extension before the `.gz`, see `document_extension`.
+///
+/// Documents with an `http://` or `https://` URL as their path are downloaded first, which needs
+/// the `http` feature.
+///
+/// # Arguments
+///
+/// * `doc` - A `PathBuf` representing the path to the document.
+/// * `options` - The `ReadOptions` controlling how the text is read.
+///
+/// # Returns
+///
+/// The `DocumentText` of the document.
+///
+/// # Errors
+///
+/// This function returns an `io::Error` if the document can't be read or downloaded.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::path::PathBuf;
+/// use tf_idf::lexer::lib::{read_document, ReadOptions};
+///
+/// let doc = PathBuf::from("path/to/my/notes.txt");
+/// let text = read_document(&doc, &ReadOptions::default()).expect("Couldn't read the document");
+/// assert_eq!(text.title, None);
+/// ```
+pub fn read_document(doc: &PathBuf, options: &ReadOptions) -> io::Result<DocumentText> {
+    let extension = document_extension(doc);
+    let compressed = is_gzip(doc);
+    let remote = is_url(doc);
+
+    // Only local, uncompressed PDF documents can be opened by poppler directly
+    if extension.as_deref() == Some("pdf") && !compressed && !remote {
+        return read_pdf(doc, options);
+    }
+
+    let mut data = if remote {
+        download(doc)?
+    } else {
+        fs::read(doc)?
+    };
+    if compressed {
+        data = gunzip(&data)?;
+    }
+
+    match extension.as_deref() {
+        Some("pdf") => read_pdf_data(data, options),
+        Some("rtf") => {
+            let (text, mut fields) = rtf_to_text(&data)?;
+            let (content, invalid_chars) = clean_text(&text, options);
+
+            let title = fields
+                .iter()
+                .position(|(name, _)| name == "title")
+                .map(|position| fields.remove(position).1);
+            Ok(DocumentText {
+                content,
+                title,
+                page_starts: Vec::new(),
+                invalid_chars,
+                fields,
+            })
+        }
+        _ => {
+            let text = String::from_utf8(data)
+                .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+            let (mut fields, body) = front_matter(&text);
+            let (content, invalid_chars) = clean_text(body, options);
+
+            let title = fields
+                .iter()
+                .position(|(name, _)| name == "title")
+                .map(|position| fields.remove(position).1);
+            Ok(DocumentText {
+                content,
+                title,
+                page_starts: Vec::new(),
+                invalid_chars,
+                fields,
+            })
+        }
+    }
+}
+
+/// Collapses whitespace and strips control characters from extracted text.
+///
+/// Extracted PDF text often contains non-breaking spaces, stray control characters, invisible
+/// formatting characters and long runs of whitespace, which the delimiter splitter doesn't handle
+/// and would turn into junk tokens. This function replaces every run of Unicode whitespace
+/// (including zero width spaces) with a single space, and removes control characters, soft
+/// hyphens, zero width joiners and byte order marks.
+///
+/// # Arguments
+///
+/// * `text` - The extracted text.
+///
+/// # Returns
+///
+/// A `String` with the whitespace collapsed and the control characters removed.
+///
+/// # Examples
+///
+/// ```
+/// use tf_idf::lexer::lib::normalize_text;
+///
+/// assert_eq!(normalize_text("hello\u{00A0}\u{00A0}world"), "hello world");
+/// assert_eq!(normalize_text("line one\n\n\t line two"), "line one line two");
+/// assert_eq!(normalize_text("bell\u{0007}ed\u{0000}"), "belled");
+/// assert_eq!(normalize_text("\u{FEFF}soft\u{00AD}ware"), "software");
+/// assert_eq!(normalize_text("zero\u{200B}width"), "zero width");
+/// ```
+pub fn normalize_text(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut in_whitespace = false;
+
+    for c in text.chars() {
+        if c.is_whitespace() || c == '\u{200B}' {
+            if !in_whitespace {
+                result.push(' ');
+            }
+            in_whitespace = true;
+        } else if c.is_control() || matches!(c, '\u{00AD}' | '\u{200C}' | '\u{200D}' | '\u{FEFF}') {
+            continue;
+        } else {
+            result.push(c);
+            in_whitespace = false;
+        }
+    }
+
+    result
+}
+
+/// Rejoins words that are hyphenated across line breaks.
+///
+/// PDF text extraction frequently leaves words split as `inter-\nnational`, which would be
+/// tokenized as two separate words. This function removes a hyphen that directly follows a letter
+/// and is directly followed by a line break, together with the line break.
+///
+/// # Arguments
+///
+/// * `text` - The extracted text.
+///
+/// # Returns
+///
+/// A `String` with the hyphenated words rejoined.
+///
+/// # Examples
+///
+/// ```
+/// use tf_idf::lexer::lib::dehyphenate;
+///
+/// assert_eq!(dehyphenate("inter-\nnational"), "international");
+/// assert_eq!(dehyphenate("inter-\r\nnational"), "international");
+/// assert_eq!(dehyphenate("well-known\n- item"), "well-known\n- item");
+/// ```
+pub fn dehyphenate(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    let mut previous: Option<char> = None;
+
+    while let Some(c) = chars.next() {
+        if c == '-' && previous.is_some_and(char::is_alphabetic) {
+            let mut lookahead = chars.clone();
+            lookahead.next_if_eq(&'\r');
+            if lookahead.next_if_eq(&'\n').is_some() {
+                chars = lookahead;
+                continue;
+            }
+        }
+
+        result.push(c);
+        previous = Some(c);
+    }
+
+    result
+}
+
+/// Indexes a string that is already in memory as a document.
+///
+/// The text is tokenized with the default `Tokenizer` and its term frequencies calculated with the
+/// default `TfCap`, the settings of `IndexSettings::default()`, so the document can be added to an
+/// index created with them. Nothing is read from the file system: `label` only names the document
+/// in the results, and the document has no title, fields or modification time.
+///
+/// # Arguments
+///
+/// * `label` - The path the document is identified by in the index and the results.
+/// * `text` - The text of the document.
+///
+/// # Returns
+///
+/// The `Document` of the text.
+///
+/// # Examples
+///
+/// ```
+/// use std::path::PathBuf;
+/// use tf_idf::lexer::index::{Index, IndexSettings, SearchOpts};
+/// use tf_idf::lexer::lib::index_text;
+///
+/// let document = index_text(PathBuf::from("notes/rust"), "Rust is a memory safe language");
+/// let index = Index::new(IndexSettings::default(), vec![document]);
+///
+/// let results = index.search("memory", &SearchOpts::default());
+/// assert_eq!(results[0].path, PathBuf::from("notes/rust"));
+/// ```
+pub fn index_text(label: PathBuf, text: &str) -> Document {
+    let settings = IndexSettings::default();
+    let data = settings.tokenizer.tokenize(text);
+    let len = data.len();
+
+    Document {
+        data: DocFreq::single(label.clone(), index_data(data, settings.tf_cap)),
+        path: label,
+        last_modified: SystemTime::now(),
+        modified: None,
+        tokens: None,
+        title: None,
+        title_data: TermFreq::new(),
+        pages: None,
+        len,
+        fields: HashMap::new(),
+        raw_words: None,
+    }
 }
 
 /// Serializes a vector of documents to JSON and saves it to a file.
 ///
-/// This function takes a reference to a vector of documents (`&Vec<Document>`) and a file path as a `String`.
-/// It serializes the vector of documents into a prettified JSON string and saves it to the specified file.
+/// This function takes a reference to an index (`&Index`) and a file path as a `&Path`.
+/// It serializes the index, its settings and documents, into prettified JSON and saves it to the specified
+/// file with `write_atomically`. The JSON is streamed to the file document by document, so memory use
+/// during saving doesn't grow with the size of the index. The index is locked exclusively with
+/// `lock_index` while it is written, so concurrent saves don't write the same temporary file.
 ///
 /// # Arguments
 ///
-/// * `data` - A reference to a vector of documents (`&Vec<Document>`).
-/// * `path` - A `String` representing the file path to save the serialized JSON data.
+/// * `index` - A reference to the `Index` to save.
+/// * `path` - A `&Path` representing the file path to save the serialized JSON data.
 ///
 /// # Returns
 ///
@@ -115,13 +1243,15 @@ pub fn read_from_pdf(doc: &PathBuf) -> String {
 ///
 /// # Examples
 ///
-/// ```
+/// ```no_run
 /// use std::path::Path;
+/// use tf_idf::lexer::index::{Index, IndexSettings};
+/// use tf_idf::lexer::lib::serialize_and_save;
 ///
-/// let data: &Vec<Document> = &vec![/* ... */];
-/// let path = String::from("path/to/save.json");
+/// let index = Index::new(IndexSettings::default(), vec![/* ... */]);
+/// let path = Path::new("path/to/save.json");
 ///
-/// match serialize_and_save(data, path) {
+/// match serialize_and_save(&index, path) {
 ///     Ok(()) => {
 ///         println!("Data serialized and saved successfully.");
 ///     },
@@ -130,7 +1260,588 @@ pub fn read_from_pdf(doc: &PathBuf) -> String {
 ///     }
 /// }
 /// ```
-pub fn serialize_and_save(data: &Vec<Document>, path: String) -> io::Result<()> {
-    let serialized_data = serde_json::to_string_pretty(&data)?;
-    fs::write(&path, serialized_data)
+pub fn serialize_and_save(index: &Index, path: &Path) -> io::Result<()> {
+    let _lock = lock_index(path, true);
+    save_index(index, path)
+}
+
+/// Saves the index to `path` like `serialize_and_save`, without locking it.
+///
+/// This is for callers that already hold an exclusive lock from `lock_index`, such as while
+/// loading, checking and rebuilding the index as a whole. Taking the lock again would wait for
+/// the caller's own lock.
+///
+/// # Errors
+///
+/// This function returns an `io::Error` if the index can't be serialized or written.
+pub fn save_index(index: &Index, path: &Path) -> io::Result<()> {
+    // Written atomically, since the previous index may still be memory-mapped by another search
+    write_atomically(path, |writer| {
+        serde_json::to_writer_pretty(writer, index).map_err(io::Error::from)
+    })
+}
+
+/// Takes an advisory lock on the index at `path`, waiting until it is available.
+///
+/// Any number of processes can hold a shared lock to read the index, while a process writing it
+/// holds an exclusive lock, so concurrent runs against the same directory, such as a watching
+/// process and a manual search, take turns instead of corrupting the index. Since the index is
+/// replaced by a new file on every save, the lock is held on a `.lock` file next to it, which is
+/// created if needed.
+///
+/// Locking is best effort: if the lock file can't be created, such as next to an index in a
+/// read-only directory, or the file system doesn't support locks, the index isn't locked, with a
+/// warning.
+///
+/// # Arguments
+///
+/// * `path` - The path of the index.
+/// * `exclusive` - Whether to lock the index for writing instead of reading.
+///
+/// # Returns
+///
+/// The locked lock file, the lock being released when it is dropped, or `None` if the index
+/// couldn't be locked.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::path::Path;
+/// use tf_idf::lexer::lib::lock_index;
+///
+/// let lock = lock_index(Path::new("books/.data.json"), true);
+/// // The index can't be read or written by another process until the lock is dropped
+/// drop(lock);
+/// ```
+pub fn lock_index(path: &Path, exclusive: bool) -> Option<File> {
+    let lock_path = lock_path(path)?;
+    let locked = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&lock_path)
+        .and_then(|file| {
+            if exclusive {
+                file.lock()?;
+            } else {
+                file.lock_shared()?;
+            }
+            Ok(file)
+        });
+
+    match locked {
+        Ok(file) => Some(file),
+        Err(error) => {
+            warn!(
+                "Couldn't lock {:?}, the index isn't protected from concurrent runs: {}",
+                lock_path, error
+            );
+            None
+        }
+    }
+}
+
+/// Returns the path of the lock file of the index at `path`, see `lock_index`.
+pub fn lock_path(path: &Path) -> Option<PathBuf> {
+    let mut file_name = path.file_name()?.to_os_string();
+    file_name.push(".lock");
+    Some(path.with_file_name(file_name))
+}
+
+/// Loads the index saved at `path`.
+///
+/// The file is memory-mapped and deserialized straight from the mapped bytes, so a large index
+/// isn't copied into memory as a whole before parsing. If the file can't be mapped, on platforms
+/// or file systems without mmap support, it is read into memory instead. The index is locked
+/// shared with `lock_index` while it is read, so it isn't read while another process saves it.
+///
+/// # Arguments
+///
+/// * `path` - A `&Path` representing the file path of the saved index.
+///
+/// # Returns
+///
+/// An `io::Result` containing the deserialized `Index`.
+///
+/// # Errors
+///
+/// This function returns an `io::Error` if the file can't be opened or read, and an error of kind
+/// `io::ErrorKind::InvalidData` if it isn't a valid index, such as one saved by an older version.
+///
+/// # Examples
+///
+/// ```ignore
+/// use std::path::Path;
+///
+/// let index = load_index(Path::new("books/.data.json"))?;
+///
+/// println!("{} documents", index.documents.len());
+/// ```
+pub fn load_index(path: &Path) -> io::Result<Index> {
+    let _lock = lock_index(path, false);
+    read_index(path)
+}
+
+/// Loads the index saved at `path` like `load_index`, without locking it.
+///
+/// This is for callers that already hold a lock from `lock_index`, see `save_index`.
+///
+/// # Errors
+///
+/// This function returns the errors of `load_index`.
+pub fn read_index(path: &Path) -> io::Result<Index> {
+    let file = File::open(path)?;
+
+    // Safety: the index is only written through a temporary file, so the mapped file isn't
+    // modified while it is being parsed unless another program truncates it in place
+    let parsed = match unsafe { Mmap::map(&file) } {
+        Ok(map) => serde_json::from_slice(&map),
+        Err(_) => serde_json::from_slice(&fs::read(path)?),
+    };
+
+    parsed.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Exports the index as a term/document matrix in TSV format.
+///
+/// This function aggregates the terms of all documents and writes them to `path` with
+/// `write_atomically`, so an interrupted export never leaves a truncated matrix. In the dense
+/// format the first row holds the document paths, and every following row holds a term followed
+/// by its value in every document. In the sparse format every line is a `term\tdoc\tvalue`
+/// triplet, and only nonzero values are written, which keeps the file small for sparse corpora.
+/// Its cost depends on the number of nonzero values only. Paths and terms containing a tab, a
+/// quote or a line break are quoted like in CSV, which pandas and R read by default.
+///
+/// # Arguments
+///
+/// * `data` - The documents of the index.
+/// * `path` - The file to write the matrix to.
+/// * `sparse` - Whether to write the sparse triplet format instead of the dense matrix.
+/// * `tfidf` - Whether the values are tf-idf instead of plain term frequency.
+/// * `idf` - The formula used to calculate the inverse document frequency for tf-idf values.
+///
+/// # Errors
+///
+/// This function can return an `io::Error` if there is an issue writing to the file.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::path::Path;
+/// use tf_idf::lexer::lexing::Document;
+/// use tf_idf::lexer::lib::export_matrix;
+/// use tf_idf::lexer::scoring::IdfVariant;
+///
+/// let data: &Vec<Document> = &vec![/* ... */];
+///
+/// export_matrix(data, Path::new("matrix.tsv"), true, false, IdfVariant::Smooth)
+///     .expect("Couldn't export");
+/// ```
+pub fn export_matrix(
+    data: &[Document],
+    path: &Path,
+    sparse: bool,
+    tfidf: bool,
+    idf: IdfVariant,
+) -> io::Result<()> {
+    write_atomically(path, |writer| {
+        write_matrix(data, writer, sparse, tfidf, idf)
+    })
+}
+
+/// Writes the term/document matrix of `export_matrix` to `out`.
+fn write_matrix(
+    data: &[Document],
+    out: &mut impl Write,
+    sparse: bool,
+    tfidf: bool,
+    idf: IdfVariant,
+) -> io::Result<()> {
+    let df = document_frequencies(data);
+    // The weight of every term, computed once instead of for every cell
+    let weights: HashMap<&String, f32> = df
+        .iter()
+        .map(|(term, &df)| {
+            let weight = if tfidf { idf.idf(data.len(), df) } else { 1.0 };
+            (term, weight)
+        })
+        .collect();
+    let term_freqs: Vec<Option<&TermFreq>> = data.iter().map(Document::term_freq).collect();
+
+    if sparse {
+        // Only the terms each document contains are visited, in the order of the dense matrix
+        let weights = &weights;
+        let mut cells: Vec<(&String, usize, f32)> = term_freqs
+            .iter()
+            .enumerate()
+            .flat_map(|(doc, &term_freq)| {
+                term_freq
+                    .into_iter()
+                    .flatten()
+                    .map(move |(term, tf)| (term, doc, tf * weights[term]))
+            })
+            .filter(|&(_, _, value)| value != 0.0)
+            .collect();
+        cells.sort_unstable_by(|a, b| a.0.cmp(b.0).then(a.1.cmp(&b.1)));
+
+        writeln!(out, "term\tdoc\tvalue")?;
+        for (term, doc, value) in cells {
+            let path = data[doc].path.to_string_lossy();
+            writeln!(out, "{}\t{}\t{}", tsv_field(term), tsv_field(&path), value)?;
+        }
+    } else {
+        let terms: BTreeSet<&String> = df.keys().collect();
+
+        write!(out, "term")?;
+        for doc in data {
+            write!(out, "\t{}", tsv_field(&doc.path.to_string_lossy()))?;
+        }
+        writeln!(out)?;
+
+        for term in terms {
+            write!(out, "{}", tsv_field(term))?;
+            for term_freq in &term_freqs {
+                let tf = term_freq
+                    .and_then(|term_freq| term_freq.get(term))
+                    .copied()
+                    .unwrap_or(0.0);
+                write!(out, "\t{}", tf * weights[term])?;
+            }
+            writeln!(out)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes the vocabulary of the index, every term with its total count and document frequency.
+///
+/// The terms are sorted by descending count, see `vocabulary`. In the plain format every line
+/// holds a term, its count and its document frequency separated by spaces. The CSV format has a
+/// `term,count,df` header, and terms containing commas or quotes are quoted.
+///
+/// # Arguments
+///
+/// * `data` - The documents of the index.
+/// * `out` - The writer to write the vocabulary to.
+/// * `csv` - Whether to write CSV instead of the plain format.
+///
+/// # Errors
+///
+/// This function can return an `io::Error` if there is an issue writing to `out`.
+///
+/// # Examples
+///
+/// ```
+/// use std::io;
+/// use tf_idf::lexer::lexing::Document;
+/// use tf_idf::lexer::lib::export_vocabulary;
+///
+/// let data: &Vec<Document> = &vec![/* ... */];
+///
+/// export_vocabulary(data, &mut io::stdout().lock(), false).expect("Couldn't export");
+/// ```
+pub fn export_vocabulary(data: &[Document], out: &mut impl Write, csv: bool) -> io::Result<()> {
+    if csv {
+        writeln!(out, "term,count,df")?;
+    }
+
+    for (term, count, df) in vocabulary(data) {
+        if csv {
+            writeln!(out, "{},{},{}", csv_field(&term), count, df)?;
+        } else {
+            writeln!(out, "{} {} {}", term, count, df)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes the cosine similarity between the documents of the index, see
+/// `Index::document_similarities`.
+///
+/// Without `neighbors`, the dense similarity matrix is written: in CSV, a header row with the
+/// document paths and a row for every document starting with its path, and in JSON, an object
+/// with the `documents` and the `matrix` as an array of rows. Every document is fully similar to
+/// itself. With `neighbors`, only the most similar documents of every document are written: in
+/// CSV, `doc,neighbor,similarity` rows, and in JSON, an array of objects with the `path` and
+/// `neighbors` of every document.
+///
+/// # Arguments
+///
+/// * `index` - The index to compare the documents of.
+/// * `out` - The writer to write the similarities to.
+/// * `idf` - The formula used to calculate the inverse document frequency.
+/// * `neighbors` - The number of most similar documents to write for every document, or `None`
+///   for the dense matrix.
+/// * `json` - Whether to write JSON instead of CSV.
+///
+/// # Errors
+///
+/// This function can return an `io::Error` if there is an issue writing to `out`.
+///
+/// # Examples
+///
+/// ```ignore
+/// let index = load_index(Path::new("books/.data.json"))?;
+///
+/// export_similarity(&index, &mut io::stdout().lock(), IdfVariant::Smooth, Some(5), false)?;
+/// ```
+pub fn export_similarity(
+    index: &Index,
+    out: &mut impl Write,
+    idf: IdfVariant,
+    neighbors: Option<usize>,
+    json: bool,
+) -> io::Result<()> {
+    let docs = &index.documents;
+    let similarities = index.document_similarities(idf, neighbors);
+
+    match (neighbors, json) {
+        (Some(_), false) => {
+            writeln!(out, "doc,neighbor,similarity")?;
+            for (doc, row) in docs.iter().zip(&similarities) {
+                for (other, similarity) in row {
+                    let path = csv_field(&doc.path.to_string_lossy()).into_owned();
+                    let other = csv_field(&docs[*other].path.to_string_lossy()).into_owned();
+                    writeln!(out, "{},{},{}", path, other, similarity)?;
+                }
+            }
+        }
+        (Some(_), true) => {
+            let value: Vec<serde_json::Value> = docs
+                .iter()
+                .zip(&similarities)
+                .map(|(doc, row)| {
+                    let neighbors: Vec<serde_json::Value> = row
+                        .iter()
+                        .map(|(other, similarity)| {
+                            serde_json::json!({ "path": docs[*other].path, "similarity": similarity })
+                        })
+                        .collect();
+                    serde_json::json!({ "path": doc.path, "neighbors": neighbors })
+                })
+                .collect();
+            serde_json::to_writer_pretty(&mut *out, &value)?;
+            writeln!(out)?;
+        }
+        (None, json) => {
+            let matrix: Vec<Vec<f32>> = similarities
+                .iter()
+                .enumerate()
+                .map(|(idx, row)| {
+                    let mut dense = vec![0.0; docs.len()];
+                    dense[idx] = 1.0;
+                    for (other, similarity) in row {
+                        dense[*other] = *similarity;
+                    }
+                    dense
+                })
+                .collect();
+
+            if json {
+                let paths: Vec<&PathBuf> = docs.iter().map(|doc| &doc.path).collect();
+                let value = serde_json::json!({ "documents": paths, "matrix": matrix });
+                serde_json::to_writer_pretty(&mut *out, &value)?;
+                writeln!(out)?;
+            } else {
+                write!(out, "doc")?;
+                for doc in docs {
+                    write!(out, ",{}", csv_field(&doc.path.to_string_lossy()))?;
+                }
+                writeln!(out)?;
+                for (doc, row) in docs.iter().zip(&matrix) {
+                    write!(out, "{}", csv_field(&doc.path.to_string_lossy()))?;
+                    for similarity in row {
+                        write!(out, ",{}", similarity)?;
+                    }
+                    writeln!(out)?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Quotes a CSV field if it contains a comma, a quote or a line break.
+fn csv_field(field: &str) -> Cow<'_, str> {
+    if field.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+/// Quotes a TSV field if it contains a tab, a quote or a line break, like `csv_field`.
+fn tsv_field(field: &str) -> Cow<'_, str> {
+    if field.contains(['\t', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+/// Writes to `path` atomically with the given function.
+///
+/// The `write` function writes to a buffered temporary file next to `path`, which is then
+/// renamed to `path`. Readers therefore see either the old file or the complete new file, never
+/// a partially written one. Since the output is streamed to the file, it never has to be held in
+/// memory as a whole.
+///
+/// # Arguments
+///
+/// * `path` - The file to write.
+/// * `write` - The function writing the contents to the temporary file.
+///
+/// # Errors
+///
+/// This function can return an `io::Error` returned by `write`, or if there is an issue writing
+/// or renaming the file. The temporary file is left behind when writing fails.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::io::Write;
+/// use std::path::Path;
+/// use tf_idf::lexer::lib::write_atomically;
+///
+/// write_atomically(Path::new("results.txt"), |writer| {
+///     writeln!(writer, "1: \"book.pdf\", 0.5")
+/// })
+/// .expect("Couldn't write results");
+/// ```
+pub fn write_atomically(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Path has no file name"))?
+        .to_os_string();
+    file_name.push(".tmp");
+    let temp_path = path.with_file_name(file_name);
+
+    let mut writer = BufWriter::new(File::create(&temp_path)?);
+    write(&mut writer)?;
+    let file = writer.into_inner().map_err(|e| e.into_error())?;
+    file.sync_all()?;
+    drop(file);
+
+    fs::rename(&temp_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cp1252, index_text, normalize_text, rtf_to_text, write_matrix, IdfVariant};
+    use std::io;
+    use std::path::PathBuf;
+
+    fn matrix(sparse: bool, tfidf: bool) -> String {
+        let data = vec![
+            index_text(PathBuf::from("a.txt"), "rust memory rust"),
+            index_text(PathBuf::from("tab\tname.txt"), "memory safety"),
+        ];
+        let mut out: Vec<u8> = Vec::new();
+        write_matrix(&data, &mut out, sparse, tfidf, IdfVariant::Ln).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn normalize_text_collapses_nbsp_runs() {
+        assert_eq!(normalize_text("hello\u{00A0}\u{00A0}world"), "hello world");
+        assert_eq!(normalize_text("a \u{00A0} \u{202F}b"), "a b");
+    }
+
+    #[test]
+    fn normalize_text_collapses_tabs_and_newlines() {
+        assert_eq!(
+            normalize_text("line one\n\n\t line two"),
+            "line one line two"
+        );
+        assert_eq!(normalize_text("cell\tcell\r\nrow"), "cell cell row");
+    }
+
+    #[test]
+    fn normalize_text_strips_c0_controls() {
+        assert_eq!(normalize_text("bell\u{0007}ed\u{0000}"), "belled");
+        assert_eq!(normalize_text("\u{001B}[0mplain\u{007F}"), "[0mplain");
+    }
+
+    #[test]
+    fn normalize_text_strips_soft_hyphens_and_bom() {
+        assert_eq!(normalize_text("\u{FEFF}soft\u{00AD}ware"), "software");
+        assert_eq!(normalize_text("zero\u{200D}joiner\u{200C}s"), "zerojoiners");
+    }
+
+    #[test]
+    fn normalize_text_treats_zero_width_spaces_as_whitespace() {
+        assert_eq!(normalize_text("zero\u{200B}width"), "zero width");
+        assert_eq!(normalize_text("a\u{200B} \u{200B}b"), "a b");
+    }
+
+    #[test]
+    fn cp1252_decodes_the_windows_range() {
+        let decoded: String = (0x80..=0x9f).map(cp1252).collect();
+        assert_eq!(
+            decoded,
+            "€\u{81}‚ƒ„…†‡ˆ‰Š‹Œ\u{8D}Ž\u{8F}\u{90}‘’“”•–—˜™š›œ\u{9D}žŸ"
+        );
+        assert_eq!(cp1252(b'a'), 'a');
+        assert_eq!(cp1252(0xe9), 'é');
+    }
+
+    #[test]
+    fn write_matrix_sparse_lists_the_nonzero_cells_by_term_and_document() {
+        assert_eq!(
+            matrix(true, false),
+            "term\tdoc\tvalue\n\
+             memory\ta.txt\t0.33333334\n\
+             memory\t\"tab\tname.txt\"\t0.5\n\
+             rust\ta.txt\t0.6666667\n\
+             safety\t\"tab\tname.txt\"\t0.5\n"
+        );
+        let rust = format!(
+            "rust\ta.txt\t{}\n",
+            (2.0_f32 / 3.0) * IdfVariant::Ln.idf(2, 1)
+        );
+        assert!(matrix(true, true).contains(&rust));
+    }
+
+    #[test]
+    fn write_matrix_dense_quotes_paths_with_tabs() {
+        assert_eq!(
+            matrix(false, false),
+            "term\ta.txt\t\"tab\tname.txt\"\n\
+             memory\t0.33333334\t0.5\n\
+             rust\t0.6666667\t0\n\
+             safety\t0\t0.5\n"
+        );
+    }
+
+    #[test]
+    fn rtf_to_text_skips_binary_data() {
+        let rtf = b"{\\rtf1\\ansi Hello {\\*\\shppict{\\pict\\bin4 }}}\x01}} world}";
+        let (text, _) = rtf_to_text(rtf).expect("Couldn't read the document");
+        assert_eq!(text, "Hello  world");
+
+        let rtf = b"{\\rtf1 Hello {\\pict\\bin2 {{} world}";
+        let (text, _) = rtf_to_text(rtf).expect("Couldn't read the document");
+        assert_eq!(text, "Hello  world");
+    }
+
+    #[test]
+    fn rtf_to_text_rejects_malformed_documents() {
+        for rtf in [
+            &b"Hello {\\b world}"[..],
+            b"{\\rtf1 Hello {\\b world}",
+            b"{\\rtf1 caf\\'zz}",
+            b"{\\rtf1 Hello \\",
+            b"{\\rtf1 Hello {\\pict\\bin100 }}",
+        ] {
+            let error = rtf_to_text(rtf).expect_err("Malformed document was read");
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+    }
 }
@@ -1,2 +1,4 @@
+pub mod index;
 pub mod lexing;
 pub mod lib;
+pub mod scoring;
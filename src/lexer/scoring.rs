@@ -0,0 +1,596 @@
+use super::lexing::Document;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+use std::time::SystemTime;
+
+/// Represents how the summed score of a multi-term query is normalized.
+///
+/// Summed scores of queries with a different number of terms aren't comparable, since every
+/// additional term can only add to the score. Normalizing by the query length makes them so.
+///
+/// # Variants
+///
+/// * `None` - The summed score is used as is.
+/// * `Terms` - The summed score is divided by the number of query terms.
+/// * `Norm` - The summed score is divided by the euclidean norm of the query term counts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QueryNorm {
+    #[default]
+    None,
+    Terms,
+    Norm,
+}
+
+impl QueryNorm {
+    /// Normalizes the summed score of a query consisting of `terms`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tf_idf::lexer::scoring::QueryNorm;
+    ///
+    /// let terms = vec!["rust".to_string(), "rust".to_string(), "memory".to_string()];
+    ///
+    /// assert_eq!(QueryNorm::None.apply(3.0, &terms), 3.0);
+    /// assert_eq!(QueryNorm::Terms.apply(3.0, &terms), 1.0);
+    /// assert_eq!(QueryNorm::Norm.apply(5f32.sqrt(), &terms), 1.0);
+    /// ```
+    pub fn apply(&self, score: f32, terms: &[String]) -> f32 {
+        let divisor = match self {
+            QueryNorm::None => return score,
+            QueryNorm::Terms => terms.len() as f32,
+            QueryNorm::Norm => {
+                let mut counts: Vec<(&String, f32)> = Vec::new();
+                for term in terms {
+                    match counts.iter_mut().find(|(seen, _)| *seen == term) {
+                        Some((_, count)) => *count += 1.0,
+                        None => counts.push((term, 1.0)),
+                    }
+                }
+                counts
+                    .iter()
+                    .map(|(_, count)| count * count)
+                    .sum::<f32>()
+                    .sqrt()
+            }
+        };
+
+        if divisor > 0.0 {
+            score / divisor
+        } else {
+            score
+        }
+    }
+}
+
+impl FromStr for QueryNorm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(QueryNorm::None),
+            "terms" => Ok(QueryNorm::Terms),
+            "norm" => Ok(QueryNorm::Norm),
+            _ => Err(format!("Unknown query normalization: {}", s)),
+        }
+    }
+}
+
+/// Counts the number of documents each term occurs in, either in the body, the title or another
+/// field.
+///
+/// # Arguments
+///
+/// * `docs` - The documents of the index.
+///
+/// # Returns
+///
+/// A `HashMap` mapping every term of the index to its document frequency.
+pub fn document_frequencies(docs: &[Document]) -> HashMap<String, usize> {
+    let mut df: HashMap<String, usize> = HashMap::new();
+
+    for doc in docs {
+        for term in document_terms(doc) {
+            *df.entry(term.clone()).or_insert(0) += 1;
+        }
+    }
+
+    df
+}
+
+/// Counts how often every term of the index occurs in the corpus.
+///
+/// The count of a term in a document is estimated from its term frequency and the length of the
+/// document. With the default `TfCap::None` this is the exact number of occurrences in the body.
+/// Terms that only occur in titles or other fields count zero.
+///
+/// # Arguments
+///
+/// * `docs` - The documents of the index.
+///
+/// # Returns
+///
+/// Every term of the index with its total count and its document frequency, sorted by
+/// descending count, then by descending document frequency and alphabetically.
+///
+/// # Examples
+///
+/// ```ignore
+/// for (term, count, df) in vocabulary(&index.documents).iter().take(10) {
+///     println!("{} {} {}", term, count, df);
+/// }
+/// ```
+pub fn vocabulary(docs: &[Document]) -> Vec<(String, usize, usize)> {
+    let mut counts: HashMap<&String, f32> = HashMap::new();
+
+    for doc in docs {
+        for (term, tf) in doc.term_freq().into_iter().flatten() {
+            *counts.entry(term).or_insert(0.0) += tf * doc.len as f32;
+        }
+    }
+
+    let mut vocabulary: Vec<(String, usize, usize)> = document_frequencies(docs)
+        .into_iter()
+        .map(|(term, df)| {
+            let count = counts.get(&term).map_or(0, |count| count.round() as usize);
+            (term, count, df)
+        })
+        .collect();
+    vocabulary.sort_by(|a, b| {
+        b.1.cmp(&a.1)
+            .then_with(|| b.2.cmp(&a.2))
+            .then_with(|| a.0.cmp(&b.0))
+    });
+
+    vocabulary
+}
+
+/// Returns the distinct terms of a document, in the body, the title or another field, which are
+/// the terms it counts towards the document frequency of.
+pub fn document_terms(doc: &Document) -> HashSet<&String> {
+    let body = doc
+        .term_freq()
+        .into_iter()
+        .flat_map(|term_freq| term_freq.keys());
+    let fields = doc.fields.values().flat_map(|term_freq| term_freq.keys());
+
+    body.chain(doc.title_data.keys()).chain(fields).collect()
+}
+
+/// The smallest inverse document frequency a term can have.
+///
+/// With `ln(N / df)` a term that occurs in every document gets an IDF of zero, so a query for it
+/// would score every document zero and rank nothing. Flooring the IDF to a small positive value
+/// keeps such terms rankable by their term frequency, while their weight stays negligible next to
+/// rarer terms of the same query.
+pub const MIN_IDF: f32 = 1e-3;
+
+/// Represents the formula used to calculate the inverse document frequency of a term.
+///
+/// # Variants
+///
+/// * `Ln` - `ln(N / df)`.
+/// * `Log10` - `log10(N / df)`.
+/// * `Smooth` - `ln(1 + N / df)`. This is the default, since it never reaches zero, so terms that
+///   occur in every document still contribute to the score.
+/// * `Probabilistic` - `ln((N - df + 0.5) / (df + 0.5))`, as used by BM25. This is negative for
+///   terms that occur in more than half of the documents.
+///
+/// Whatever the formula, the result is floored to `MIN_IDF`. A term that occurs in no document,
+/// for which most formulas divide by zero, has an IDF of zero instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IdfVariant {
+    Ln,
+    Log10,
+    #[default]
+    Smooth,
+    Probabilistic,
+}
+
+impl IdfVariant {
+    /// Calculates the inverse document frequency of a term, floored to `MIN_IDF`.
+    ///
+    /// A `df` of zero is handled before the division: the term matches nothing, so its IDF is
+    /// zero, rather than an infinite value that would turn the scores into NaN or infinity.
+    ///
+    /// # Arguments
+    ///
+    /// * `n_docs` - The number of documents in the index.
+    /// * `df` - The number of documents the term occurs in.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tf_idf::lexer::scoring::{IdfVariant, MIN_IDF};
+    ///
+    /// assert_eq!(IdfVariant::Ln.idf(4, 4), MIN_IDF);
+    /// assert_eq!(IdfVariant::Smooth.idf(4, 0), 0.0);
+    /// assert_eq!(IdfVariant::Probabilistic.idf(4, 4), MIN_IDF);
+    /// assert!(IdfVariant::Smooth.idf(4, 4) > 0.0);
+    /// assert!(IdfVariant::Log10.idf(4, 1) > IdfVariant::Log10.idf(4, 2));
+    /// ```
+    pub fn idf(&self, n_docs: usize, df: usize) -> f32 {
+        if df == 0 {
+            return 0.0;
+        }
+        let n = n_docs as f32;
+        let df = df as f32;
+
+        let idf = match self {
+            IdfVariant::Ln => (n / df).ln(),
+            IdfVariant::Log10 => (n / df).log10(),
+            IdfVariant::Smooth => (1.0 + n / df).ln(),
+            IdfVariant::Probabilistic => ((n - df + 0.5) / (df + 0.5)).ln(),
+        };
+
+        idf.max(MIN_IDF)
+    }
+}
+
+impl FromStr for IdfVariant {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ln" => Ok(IdfVariant::Ln),
+            "log10" => Ok(IdfVariant::Log10),
+            "smooth" => Ok(IdfVariant::Smooth),
+            "probabilistic" => Ok(IdfVariant::Probabilistic),
+            _ => Err(format!("Unknown idf variant: {}", s)),
+        }
+    }
+}
+
+/// A formula scoring how relevant a document is to a single query term.
+///
+/// `Index::search_with` sums the scores of all query terms of a document, so implementing this
+/// trait is enough to experiment with a different ranking formula. The boosts, the query
+/// normalization and the operator of the `SearchOpts` are applied on top of it.
+///
+/// # Examples
+///
+/// ```ignore
+/// struct Binary;
+///
+/// impl Scorer for Binary {
+///     fn score(&self, tf: f32, _df: usize, _n_docs: usize, _doc_len: usize, _avg_len: f32) -> f32 {
+///         if tf > 0.0 { 1.0 } else { 0.0 }
+///     }
+/// }
+///
+/// let results = index.search_with("memory safety", &SearchOpts::default(), &Binary);
+/// ```
+pub trait Scorer {
+    /// Scores a document for a single query term.
+    ///
+    /// # Arguments
+    ///
+    /// * `tf` - The term frequency of the term in the document, as stored in the index, plus the
+    ///   boosted term frequency in the title. Without a `TfCap` this is the number of occurrences
+    ///   divided by `doc_len`.
+    /// * `df` - The number of documents the term occurs in, at least one.
+    /// * `n_docs` - The number of documents in the index.
+    /// * `doc_len` - The number of terms in the document.
+    /// * `avg_len` - The average number of terms in the documents of the index.
+    fn score(&self, tf: f32, df: usize, n_docs: usize, doc_len: usize, avg_len: f32) -> f32;
+}
+
+/// The `Scorer` of the term frequency alone, without weighting rare terms higher.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TfScorer;
+
+impl Scorer for TfScorer {
+    fn score(&self, tf: f32, _df: usize, _n_docs: usize, _doc_len: usize, _avg_len: f32) -> f32 {
+        tf
+    }
+}
+
+/// The default `Scorer`, the term frequency multiplied by the inverse document frequency.
+///
+/// # Fields
+///
+/// * `idf` - The formula used to calculate the inverse document frequency.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TfIdfScorer {
+    pub idf: IdfVariant,
+}
+
+impl Scorer for TfIdfScorer {
+    fn score(&self, tf: f32, df: usize, n_docs: usize, _doc_len: usize, _avg_len: f32) -> f32 {
+        tf * self.idf.idf(n_docs, df)
+    }
+}
+
+/// The Okapi BM25 `Scorer`.
+///
+/// The term frequency saturates, so repeating a term has diminishing returns, and is normalized
+/// by the length of the document relative to the average length. The number of occurrences is
+/// recovered from the stored term frequency, so this is only meaningful for indexes built without
+/// a `TfCap`. Documents of an index without document lengths are treated as of average length.
+///
+/// # Fields
+///
+/// * `k1` - How quickly the term frequency saturates. The default is 1.2.
+/// * `b` - How much the document length normalizes the term frequency, from 0 (not at all) to 1
+///   (fully). The default is 0.75.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bm25Scorer {
+    pub k1: f32,
+    pub b: f32,
+}
+
+impl Default for Bm25Scorer {
+    fn default() -> Self {
+        Bm25Scorer { k1: 1.2, b: 0.75 }
+    }
+}
+
+impl Scorer for Bm25Scorer {
+    fn score(&self, tf: f32, df: usize, n_docs: usize, doc_len: usize, avg_len: f32) -> f32 {
+        // A term that occurs in no document contributes nothing, like with the other scorers
+        if df == 0 {
+            return 0.0;
+        }
+        let (doc_len, avg_len) = if doc_len == 0 || avg_len <= 0.0 {
+            (1.0, 1.0)
+        } else {
+            (doc_len as f32, avg_len)
+        };
+        let count = tf * doc_len;
+        let idf = (1.0 + (n_docs as f32 - df as f32 + 0.5) / (df as f32 + 0.5)).ln();
+
+        idf * count * (self.k1 + 1.0)
+            / (count + self.k1 * (1.0 - self.b + self.b * doc_len / avg_len))
+    }
+}
+
+/// Represents the built-in ranking formulas that can be selected on the command line.
+///
+/// # Variants
+///
+/// * `Tf` - The `TfScorer`.
+/// * `TfIdf` - The `TfIdfScorer`. This is the default.
+/// * `Bm25` - The `Bm25Scorer` with its default parameters.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Ranking {
+    Tf,
+    #[default]
+    TfIdf,
+    Bm25,
+}
+
+impl Ranking {
+    /// Returns the `Scorer` of the ranking formula, using `idf` for tf-idf.
+    pub fn scorer(&self, idf: IdfVariant) -> Box<dyn Scorer> {
+        match self {
+            Ranking::Tf => Box::new(TfScorer),
+            Ranking::TfIdf => Box::new(TfIdfScorer { idf }),
+            Ranking::Bm25 => Box::new(Bm25Scorer::default()),
+        }
+    }
+}
+
+impl FromStr for Ranking {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tf" => Ok(Ranking::Tf),
+            "tfidf" => Ok(Ranking::TfIdf),
+            "bm25" => Ok(Ranking::Bm25),
+            _ => Err(format!("Unknown scorer: {}", s)),
+        }
+    }
+}
+
+/// Finds the length of the shortest window of tokens containing all of the given terms.
+///
+/// This function slides a window over `tokens` and returns the number of tokens in the smallest
+/// window that contains every distinct term of `terms` at least once.
+///
+/// # Arguments
+///
+/// * `tokens` - The ordered tokens of a document.
+/// * `terms` - The query terms.
+///
+/// # Returns
+///
+/// `Some(usize)` with the length of the window, or `None` if any of the terms doesn't occur in
+/// the tokens.
+///
+/// # Examples
+///
+/// ```
+/// use tf_idf::lexer::lexing::split_into_words;
+/// use tf_idf::lexer::scoring::min_span;
+///
+/// let tokens = split_into_words("rust is fast and rust manages memory safely");
+/// let terms = vec!["rust".to_string(), "memory".to_string()];
+///
+/// assert_eq!(min_span(&tokens, &terms), Some(3));
+/// assert_eq!(min_span(&tokens, &["python".to_string()]), None);
+/// ```
+pub fn min_span(tokens: &[String], terms: &[String]) -> Option<usize> {
+    let wanted: HashSet<&String> = terms.iter().collect();
+    let mut counts: HashMap<&String, usize> = HashMap::new();
+    let mut best: Option<usize> = None;
+    let mut start = 0;
+
+    for (end, token) in tokens.iter().enumerate() {
+        if !wanted.contains(token) {
+            continue;
+        }
+        *counts.entry(token).or_insert(0) += 1;
+
+        while counts.len() == wanted.len() {
+            let span = end - start + 1;
+            if best.is_none_or(|best| span < best) {
+                best = Some(span);
+            }
+
+            let first = &tokens[start];
+            if let Some(count) = counts.get_mut(first) {
+                *count -= 1;
+                if *count == 0 {
+                    counts.remove(first);
+                }
+            }
+            start += 1;
+        }
+    }
+
+    best
+}
+
+/// Calculates the multiplier rewarding query terms that appear close together.
+///
+/// The multiplier is `1 + boost * k / span`, where `k` is the number of distinct query terms and
+/// `span` is the shortest window of tokens containing all of them, as found by `min_span`. When
+/// the terms are adjacent the multiplier is `1 + boost`, and it approaches 1 as they spread apart.
+/// Queries with a single distinct term, and documents missing any of the terms, get 1.
+///
+/// # Examples
+///
+/// ```
+/// use tf_idf::lexer::lexing::split_into_words;
+/// use tf_idf::lexer::scoring::proximity_factor;
+///
+/// let tokens = split_into_words("memory safety in rust");
+/// let terms = vec!["rust".to_string(), "memory".to_string()];
+///
+/// assert_eq!(proximity_factor(&tokens, &terms, 1.0), 1.5);
+/// ```
+pub fn proximity_factor(tokens: &[String], terms: &[String], boost: f32) -> f32 {
+    let distinct = terms.iter().collect::<HashSet<_>>().len();
+    if distinct < 2 {
+        return 1.0;
+    }
+
+    match min_span(tokens, terms) {
+        Some(span) => 1.0 + boost * distinct as f32 / span as f32,
+        None => 1.0,
+    }
+}
+
+/// Calculates the multiplier rewarding query words that occur in a document exactly as typed.
+///
+/// The multiplier is `1 + boost * k / n`, where `n` is the number of query words and `k` the
+/// number of them among the `raw_words` of the document, so a document containing every query
+/// word as typed gets `1 + boost`. Queries without words get 1.
+///
+/// # Examples
+///
+/// ```
+/// use tf_idf::lexer::lexing::raw_words;
+/// use tf_idf::lexer::scoring::exact_factor;
+///
+/// let words = raw_words("Memory safety in Rust");
+/// let query = vec!["Rust".to_string(), "memory".to_string()];
+///
+/// assert_eq!(exact_factor(&words, &query, 1.0), 1.5);
+/// ```
+pub fn exact_factor(raw_words: &HashSet<String>, query: &[String], boost: f32) -> f32 {
+    if query.is_empty() {
+        return 1.0;
+    }
+
+    let exact = query
+        .iter()
+        .filter(|word| raw_words.contains(*word))
+        .count();
+    1.0 + boost * exact as f32 / query.len() as f32
+}
+
+/// The number of seconds in a day, the unit of the recency half-life.
+const DAY_IN_SECONDS: f32 = 86400.0;
+
+/// Calculates the multiplier favoring recently modified documents.
+///
+/// The multiplier decays exponentially with the age of the document: it is 1 for a document
+/// modified `now`, 0.5 for one modified `half_life` days ago, 0.25 for twice that age, and so on.
+/// Modification times in the future count as `now`.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::{Duration, SystemTime};
+/// use tf_idf::lexer::scoring::recency_factor;
+///
+/// let now = SystemTime::now();
+/// let month_ago = now - Duration::from_secs(30 * 86400);
+///
+/// assert_eq!(recency_factor(now, now, 30.0), 1.0);
+/// assert_eq!(recency_factor(month_ago, now, 30.0), 0.5);
+/// ```
+pub fn recency_factor(modified: SystemTime, now: SystemTime, half_life: f32) -> f32 {
+    let age = now
+        .duration_since(modified)
+        .map(|age| age.as_secs_f32() / DAY_IN_SECONDS)
+        .unwrap_or(0.0);
+
+    0.5f32.powf(age / half_life)
+}
+
+/// The number of hash functions of a MinHash signature. More make the similarity estimates more
+/// precise, with an error of about `1 / sqrt(MINHASH_SIZE)`.
+pub const MINHASH_SIZE: usize = 128;
+
+/// Calculates the MinHash signature of a set of terms.
+///
+/// The signature holds the smallest hash of the terms under each of `MINHASH_SIZE` hash
+/// functions. The fraction of positions in which the signatures of two sets agree estimates the
+/// Jaccard similarity of the sets, see `minhash_similarity`, without comparing the sets
+/// themselves. The hash functions are derived from a single hash of every term by double
+/// hashing, and are the same in every run.
+///
+/// # Examples
+///
+/// ```
+/// use tf_idf::lexer::scoring::{minhash, minhash_similarity, MINHASH_SIZE};
+///
+/// let a = minhash(["rust", "memory", "safety"]);
+/// let b = minhash(["rust", "memory", "safety"]);
+///
+/// assert_eq!(a.len(), MINHASH_SIZE);
+/// assert_eq!(minhash_similarity(&a, &b), 1.0);
+/// ```
+pub fn minhash<'a>(terms: impl IntoIterator<Item = &'a str>) -> Vec<u64> {
+    let mut signature = vec![u64::MAX; MINHASH_SIZE];
+
+    for term in terms {
+        let mut hasher = DefaultHasher::new();
+        term.hash(&mut hasher);
+        let hash = hasher.finish();
+        let (h1, h2) = (hash & 0xFFFF_FFFF, (hash >> 32) | 1);
+
+        for (i, min) in signature.iter_mut().enumerate() {
+            let value = h1
+                .wrapping_add((i as u64).wrapping_mul(h2))
+                .wrapping_mul(MIX);
+            *min = (*min).min(value);
+        }
+    }
+
+    signature
+}
+
+/// An odd constant that spreads the bits of the derived hashes.
+const MIX: u64 = 0x9E37_79B9_7F4A_7C15;
+
+/// Estimates the Jaccard similarity of two sets from their `minhash` signatures.
+///
+/// # Returns
+///
+/// The fraction of positions in which the signatures agree, between 0 for sets without common
+/// terms and 1 for identical sets. Two empty sets aren't similar.
+pub fn minhash_similarity(a: &[u64], b: &[u64]) -> f32 {
+    let agree = a
+        .iter()
+        .zip(b)
+        .filter(|(a, b)| a == b && **a != u64::MAX)
+        .count();
+
+    agree as f32 / a.len().max(1) as f32
+}
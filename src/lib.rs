@@ -0,0 +1,5 @@
+//! Indexing and tf-idf search of PDF and plain text documents.
+//!
+//! The `tf-idf` binary is a command line interface to this library. Embedding applications load
+//! an `Index` with `lexer::lib::load_index` and search it with `Index::search`.
+pub mod lexer;
@@ -1,112 +1,1288 @@
-mod lexer;
-use lexer::{
-    lexing::{index_data, split_into_words, DocFreq, DocFreqExt, Document, Idf},
-    lib::{read_from_pdf, search_filetype, serialize_and_save},
-};
-use serde_json::Result;
-use std::fs::read_to_string;
+mod options;
+use log::{debug, info, warn, Level, LevelFilter};
+use options::{Command, Duplicates, Options, SettingsChange, Verbosity};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, Result, Write};
 use std::{
     path::{Path, PathBuf},
-    time::{Duration, SystemTime},
+    process::ExitCode,
+    thread::{self, JoinHandle},
+    time::{Duration, Instant, SystemTime},
+};
+use tf_idf::lexer::{
+    index::{parse_query, Index, IndexSettings},
+    lexing::{
+        best_snippet, index_data, index_field_data, index_pages, path_terms, raw_words, snippet,
+        DocFreq, DocFreqExt, Document, Idf, SnippetStrategy, TermScore,
+    },
+    lib::{
+        export_matrix, export_similarity, export_vocabulary, is_url, load_index, lock_index,
+        lock_path, read_document, read_file_list, read_index, save_index, search_filetype,
+        write_atomically, Symlinks,
+    },
 };
 const WEEK_IN_SECONDS: u64 = 604800;
+const SNIPPET_RADIUS: usize = 8;
 
 /// Runs the search process on the given directory and search query.
 ///
-/// This function takes a directory path, a vector of PDF file paths, and a search query string as input. It performs the search process, which includes indexing the data, checking if the indexed data needs to be updated, and performing the search query. The search results are printed to the console.
+/// This function takes a function listing the PDF file paths and the command line options as input. It performs the search process, which includes indexing the data, checking if the indexed data needs to be updated, and performing the search query. The search results are printed to stdout, or written to `options.output` if given, while status messages are logged to stderr.
+///
+/// The files are only listed when they need to be (re)indexed, so a search against a cached index doesn't need the directory to exist.
+/// If the directory is gone while the cached index is merely stale, the cached index is searched anyway, with a warning.
+///
+/// The index is read from and saved to `options.index_path()`, which defaults to `.data.json` inside the searched directory.
+/// If the directory is actually a single file, a one-document index is built in memory instead, and nothing is read or saved.
+///
+/// When the query is omitted, the directory is only indexed and the index saved.
+///
+/// For the `export-matrix` command the term/document matrix of the index is written to the output file instead of searching,
+/// and for the `vocab` command the vocabulary of the index is written to stdout, or `options.output` if given.
 ///
 /// # Arguments
 ///
-/// * `directory` - A mutable string representing the directory path.
-/// * `all_pdf_paths` - A vector of `PathBuf` representing the paths of all PDF files.
-/// * `query` - A string representing the search query.
+/// * `list_files` - A function returning the paths of all PDF files, called only when they are indexed.
+/// * `options` - The parsed command line `Options`, holding the command, the index path and the search query.
+///
+/// # Returns
+///
+/// The number of documents matching the query, or the number of indexed or exported documents for the other commands.
 ///
 /// # Errors
 ///
 /// This function can return an error if there are issues with reading or writing the data files, or if there are errors during JSON deserialization.
+/// It also returns an error, before the index is loaded, if the query has no term of at least `options.min_query_len` characters.
 ///
 /// # Examples
 ///
 /// ```
 /// use std::path::PathBuf;
 ///
-/// let directory = "data".to_string();
-/// let pdf_paths = vec![
-///     PathBuf::from("file1.pdf"),
-///     PathBuf::from("file2.pdf"),
-///     PathBuf::from("file3.pdf"),
-/// ];
+/// let pdf_paths = || {
+///     Ok(vec![
+///         PathBuf::from("file1.pdf"),
+///         PathBuf::from("file2.pdf"),
+///         PathBuf::from("file3.pdf"),
+///     ])
+/// };
+///
+/// let options = Options::parse(["pdf", "data", "example"].into_iter().map(String::from)).unwrap();
 ///
-/// run(directory, pdf_paths, "example".to_string()).expect("Search process failed");
+/// let matches = run(pdf_paths, &options).expect("Search process failed");
 /// ```
 ///
-/// The function can be used with a valid directory path, a vector of PDF file paths, and a search query string to perform the search process on the data and print the search results.
-fn run(mut directory: String, all_pdf_paths: Vec<PathBuf>, query: String) -> Result<()> {
-    let json_name = Path::new(&directory).join(".data.json");
-    if json_name.exists() {
-        let filedata = read_to_string(json_name).unwrap();
-        let data: Vec<Document> = serde_json::from_str(&filedata)?;
-        let date = data.get(0).unwrap().last_modified.elapsed().unwrap();
-
-        if date > Duration::from_secs(WEEK_IN_SECONDS) {
-            // If date saved is larger than a week we re-indexing the whole thing and then searching
-            // Reindex data and search
-            println!("Reindexing data");
-            let saved_data = tokenize_data(all_pdf_paths);
-            serialize_and_save(&saved_data, directory).expect("Couldn't serialize");
-            search_query(saved_data, query);
-            Ok(())
-        } else {
-            // Just search query
-            println!("Searching for {}", query);
-            search_query(data, query);
-            Ok(())
+/// The function can be used with a vector of PDF file paths and the options holding the directory and search query to perform the search process on the data and print the search results.
+fn run(list_files: impl Fn() -> Result<Vec<PathBuf>>, options: &Options) -> Result<usize> {
+    let query = options.query.clone();
+    let all_pdf_paths = || {
+        list_files()
+            .map(|paths| exclude_index_files(paths, &options.index_path()))
+            .and_then(|paths| dedupe_paths(paths, options.duplicates))
+            .map(|paths| check_files(paths, options))
+    };
+
+    if options.dry_run {
+        let (paths, skipped) = all_pdf_paths()?;
+        return dry_run(paths, skipped, options);
+    }
+    if matches!(options.command, Command::Verify) {
+        // Every indexed document is missing when the directory itself is gone
+        let paths = match all_pdf_paths() {
+            Ok((paths, _)) => paths,
+            Err(e) if e.kind() == io::ErrorKind::NotFound && options.files_from.is_none() => {
+                Vec::new()
+            }
+            Err(e) => return Err(e),
+        };
+        return verify(paths, options);
+    }
+
+    // A blank or stray one letter query would list every document with a zero score
+    if matches!(options.command, Command::Search) {
+        let terms = options.tokenizer.tokenize(&query);
+        if !terms
+            .iter()
+            .any(|term| term.chars().count() >= options.min_query_len)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Query {:?} has no term of at least {} characters",
+                    query, options.min_query_len
+                ),
+            ));
+        }
+    }
+    let json_name = options.index_path();
+    let settings = options.index_settings();
+    let full_reindex = || {
+        let (paths, skipped) = all_pdf_paths()?;
+        reindex(paths, skipped, Vec::new(), &json_name, options)
+    };
+    // Loading, checking and rebuilding the index is a single read-modify-write, so the index is
+    // locked for all of it: a concurrent run waits, and then finds the index this one rebuilt
+    // instead of rebuilding it again. It is only read with --no-reindex
+    let lock = if is_document(&options.directory) {
+        None
+    } else {
+        lock_index(&json_name, !options.no_reindex)
+    };
+    let index = if is_document(&options.directory) {
+        // A single file is indexed in memory only, without reading or saving the index
+        let (paths, mut skipped) = all_pdf_paths()?;
+        let (data, unindexed) = tokenize_data(paths, Vec::new(), options, None);
+        skipped.extend(unindexed);
+        for (path, reason) in &skipped {
+            info!("Skipped {:?}: {}", path, reason);
+        }
+        if let Some(report) = &options.error_report {
+            write_error_report(report, &skipped)?;
+        }
+        Index::new(settings, data)
+    } else if matches!(options.command, Command::Index) {
+        // Indexing only always builds a fresh index
+        full_reindex()?
+    } else if json_name.exists() {
+        debug!("Loading the index {:?}", json_name);
+        let loaded = read_index(&json_name);
+        let keep_settings = match &loaded {
+            Ok(index) if index.settings != settings => {
+                keep_changed_settings(&index.settings, &settings, &json_name, options)?
+            }
+            _ => false,
+        };
+        match loaded {
+            // An index saved by an older version has no settings and can't be trusted
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                if options.no_reindex {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Index {:?} is outdated and --no-reindex is set", json_name),
+                    ));
+                }
+                info!("Index format is outdated");
+                full_reindex()?
+            }
+            Err(e) => return Err(e),
+            // Query terms only match when they are tokenized the same way as the documents, and
+            // scores are only comparable when they are calculated the same way. Searching anyway
+            // would silently miss matches, so unless the user chose to search it anyway the index
+            // is rebuilt
+            Ok(index) if index.settings != settings && !keep_settings => {
+                let differences = index.settings.differences(&settings).join(", ");
+                info!(
+                    "Index settings changed since the index was built ({})",
+                    differences
+                );
+                full_reindex()?
+            }
+            // A partial index is a checkpoint of an indexing run that was interrupted, so the
+            // run is picked up where it stopped
+            Ok(index) if index.partial && !options.no_reindex => {
+                let (paths, skipped) = all_pdf_paths()?;
+                let (indexed, remaining) = split_indexed(index, paths);
+                reindex(remaining, skipped, indexed, &json_name, options)?
+            }
+            Ok(mut index) => {
+                // The stop words themselves aren't saved, only their hash
+                index.settings = settings;
+                let date = index
+                    .documents
+                    .first()
+                    .map(|doc| doc.last_modified.elapsed());
+
+                // A last modified time in the future (clock skew, files copied from a
+                // misconfigured machine) can't be trusted, so such documents make the whole
+                // index stale
+                let future: Vec<&PathBuf> = index
+                    .documents
+                    .iter()
+                    .filter(|doc| doc.last_modified.elapsed().is_err())
+                    .map(|doc| &doc.path)
+                    .collect();
+                for path in &future {
+                    warn!("{:?} has a last modified time in the future", path);
+                }
+
+                // An error from elapsed() means the index was saved in the future, and an empty
+                // index has no timestamp at all, so both are reindexed rather than trusted
+                let stale = match date {
+                    Some(Ok(date)) => date > Duration::from_secs(WEEK_IN_SECONDS),
+                    Some(Err(_)) | None => true,
+                };
+
+                if index.partial {
+                    warn!("Index is incomplete, indexing was interrupted");
+                }
+
+                // Without the files a stale index can't be rebuilt, but it can still be searched
+                let outdated = (stale || !future.is_empty()) && !options.no_reindex;
+                let unavailable = options.files_from.is_none() && !options.directory.exists();
+                if outdated && unavailable {
+                    warn!(
+                        "{:?} doesn't exist, searching the cached index without reindexing",
+                        options.directory
+                    );
+                }
+
+                if outdated && !unavailable {
+                    // If date saved is larger than a week we re-indexing the whole thing
+                    full_reindex()?
+                } else {
+                    index
+                }
+            }
         }
+    } else if options.no_reindex {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("No index at {:?} and --no-reindex is set", json_name),
+        ));
     } else {
-        // Create new file, reindex data, and search query
-        println!("Reindexing data");
-        directory.push_str(&format!("{}", ".data.json"));
-        let data = tokenize_data(all_pdf_paths);
-        serialize_and_save(&data, directory).expect("Couldn't write to file");
-        search_query(data, query);
+        // Create new file and reindex data
+        full_reindex()?
+    };
+    drop(lock);
+    let data = &index.documents;
+
+    match &options.command {
+        Command::Search => {
+            info!("Searching for {}", query);
+            if options.proximity_boost > 0.0 && data.iter().all(|doc| doc.tokens.is_none()) {
+                warn!("The proximity boost needs an index built with --store-tokens");
+            }
+            let substrings = index
+                .parse_query(&query)
+                .iter()
+                .any(|term| term.term.len() > 1 && term.term.starts_with('*'));
+            if substrings && options.substring {
+                warn!(
+                    "Substring search scans all {} terms of the index, which is slow on a large index",
+                    index.term_count()
+                );
+            } else if substrings {
+                warn!("Terms starting with * only match with --substring");
+            }
+            for path in options.boosts.keys() {
+                if !data.iter().any(|doc| &doc.path == path) {
+                    warn!("{:?} in the boosts file isn't indexed", path);
+                }
+            }
+            if options.exact_boost > 0.0 && data.iter().all(|doc| doc.raw_words.is_none()) {
+                warn!("The exact-match boost needs an index built with --store-raw-words");
+            }
+            let words: Vec<String> = index
+                .parse_query(&query)
+                .into_iter()
+                .map(|term| term.term)
+                .collect();
+            let common = index.auto_stopwords(&words, options.auto_stopwords);
+            if !common.is_empty() {
+                info!("Ignoring common terms: {}", common.join(", "));
+            }
+            // Such terms contribute nothing to any score
+            for word in &words {
+                let found = match index.expand(word, options.substring) {
+                    Some(expansion) => !expansion.is_empty(),
+                    None => index.document_frequency(word) > 0,
+                };
+                if !found {
+                    debug!("{} not found in any document", word);
+                }
+            }
+
+            let scorer = options.scorer.scorer(options.idf);
+            if options.count {
+                let count = index.count_with(&query, &options.search_opts(), scorer.as_ref());
+                match &options.output {
+                    Some(output) => {
+                        write_atomically(output, |writer| writeln!(writer, "{}", count))?
+                    }
+                    None => writeln!(io::stdout().lock(), "{}", count)?,
+                }
+                return Ok(count);
+            }
+
+            let start = Instant::now();
+            let mut results = index.search_with(&query, &options.search_opts(), scorer.as_ref());
+            if let Some(threshold) = options.collapse_near_dups {
+                let found = results.len();
+                results = index.collapse_near_duplicates(results, threshold);
+                if results.len() < found {
+                    info!("Collapsed {} near-duplicates", found - results.len());
+                }
+            }
+            if options.dedupe_results {
+                let found = results.len();
+                results = dedupe_results(results);
+                if results.len() < found {
+                    info!(
+                        "Removed {} results that are the same file as a better ranked one",
+                        found - results.len()
+                    );
+                }
+            }
+            debug!(
+                "Searched {} documents in {:.2?}",
+                data.len(),
+                start.elapsed()
+            );
+            let matches = results.iter().filter(|elem| elem.tf > 0.0).count();
+
+            match &options.output {
+                Some(output) => write_atomically(output, |writer| {
+                    write_results(writer, &results, data, &query, options)
+                })?,
+                None => write_results(&mut io::stdout().lock(), &results, data, &query, options)?,
+            }
+
+            Ok(matches)
+        }
+        Command::ExportMatrix { output } => {
+            export_matrix(data, output, options.sparse, options.tfidf, options.idf)?;
+            info!("Exported matrix to {:?}", output);
+            Ok(data.len())
+        }
+        Command::ExportSimilarity { output } => {
+            let json = output
+                .extension()
+                .is_some_and(|extension| extension.eq_ignore_ascii_case("json"));
+            write_atomically(output, |writer| {
+                export_similarity(&index, writer, options.idf, options.neighbors, json)
+            })?;
+            info!("Exported similarities to {:?}", output);
+            Ok(data.len())
+        }
+        Command::Vocab => {
+            match &options.output {
+                Some(output) => write_atomically(output, |writer| {
+                    export_vocabulary(data, writer, options.csv)
+                })?,
+                None => export_vocabulary(data, &mut io::stdout().lock(), options.csv)?,
+            }
+            Ok(index.term_count())
+        }
+        Command::Index => Ok(data.len()),
+        Command::Tokenize { input } => tokenize_input(input, options),
+        Command::Clear => clear_index(options),
+        Command::Similar { path } => {
+            let stored = indexed_path(data, path)?;
+            info!("Documents similar to {:?}", stored);
+            let results = index
+                .similar(stored, options.similar_terms, &options.search_opts())
+                .unwrap_or_default();
+
+            // The shared terms stand in for the query when highlighting snippets
+            let mut terms: Vec<&str> = results
+                .iter()
+                .flat_map(|elem| elem.explanation.iter().map(|score| score.term.as_str()))
+                .collect();
+            terms.sort_unstable();
+            terms.dedup();
+            let query = terms.join(" ");
+
+            match &options.output {
+                Some(output) => write_atomically(output, |writer| {
+                    write_results(writer, &results, data, &query, options)
+                })?,
+                None => write_results(&mut io::stdout().lock(), &results, data, &query, options)?,
+            }
+
+            Ok(results.len())
+        }
+        // Verifying never loads the index, it returns above
+        Command::Verify => unreachable!("verify is run before the index is loaded"),
+        Command::Keywords { path } => {
+            let stored = indexed_path(data, path)?;
+            info!("Keywords of {:?}", stored);
+            let keywords = index
+                .keywords(stored, options.keyword_count, &options.search_opts())
+                .unwrap_or_default();
+            write_terms(&keywords, options)?;
+
+            Ok(keywords.len())
+        }
+        Command::Cooccur { term } => {
+            let terms = options.tokenizer.tokenize(term);
+            let [term] = terms.as_slice() else {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "{:?} isn't a single term, it is tokenized as {:?}",
+                        term, terms
+                    ),
+                ));
+            };
+            info!("Terms co-occurring with {}", term);
+            if index.document_frequency(term) == 0 {
+                info!("No document contains {}", term);
+            }
+            let related = index.cooccurring(term, options.keyword_count, &options.search_opts());
+            write_terms(&related, options)?;
+
+            Ok(related.len())
+        }
+    }
+}
+
+/// Writes a ranked list of terms, such as the keywords of a document, to the output.
+///
+/// Every term is written on its own line with its rank, its score and the statistics the score
+/// is calculated from, to `options.output` if set and to stdout otherwise.
+///
+/// # Errors
+///
+/// This function returns an `io::Error` if the terms can't be written.
+fn write_terms(terms: &[TermScore], options: &Options) -> Result<()> {
+    let write = |out: &mut dyn Write| -> Result<()> {
+        for (idx, term) in terms.iter().enumerate() {
+            writeln!(
+                out,
+                "{}: {}, {} (tf={} df={} idf={})",
+                idx + 1,
+                term.term,
+                term.contribution,
+                term.tf,
+                term.df,
+                term.idf
+            )?;
+        }
         Ok(())
+    };
+    match &options.output {
+        Some(output) => write_atomically(output, |writer| write(writer)),
+        None => write(&mut io::stdout().lock()),
     }
 }
 
+/// Finds the path a document is stored under in the index.
+///
+/// The path may be spelled differently from the one stored in the index, so paths that resolve
+/// to the same file match too.
+///
+/// # Errors
+///
+/// This function returns a `NotFound` error if the document isn't in the index.
+fn indexed_path<'a>(data: &'a [Document], path: &Path) -> Result<&'a Path> {
+    let canonical = fs::canonicalize(path).ok();
+
+    data.iter()
+        .map(|doc| doc.path.as_path())
+        .find(|stored| {
+            *stored == path || (canonical.is_some() && fs::canonicalize(stored).ok() == canonical)
+        })
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{:?} isn't in the index", path),
+            )
+        })
+}
+
+/// Sets up logging of the status messages and warnings to stderr, keeping stdout for results.
+///
+/// Only the messages of this crate are logged at the level of `verbosity`, other crates only log
+/// warnings and errors. The `RUST_LOG` environment variable overrides the levels when set.
+/// Informational messages are logged as they are, other levels with the level as prefix.
+fn init_logger(verbosity: Verbosity) {
+    let level = match verbosity {
+        Verbosity::Quiet => LevelFilter::Error,
+        Verbosity::Normal => LevelFilter::Info,
+        Verbosity::Verbose => LevelFilter::Debug,
+    };
+
+    env_logger::Builder::new()
+        .filter_level(level.min(LevelFilter::Warn))
+        .filter_module("tf_idf", level)
+        .parse_default_env()
+        .format(|buf, record| match record.level() {
+            Level::Info => writeln!(buf, "{}", record.args()),
+            Level::Warn => writeln!(buf, "WARNING: {}", record.args()),
+            level => writeln!(buf, "{}: {}", level, record.args()),
+        })
+        .init();
+}
 
 // the codebase exhibits a mix of imperative and procedural programming style.
 // The code follows a step-by-step approach, performing operations sequentially and mutating state along the way.
 // It makes use of loops, conditional statements, and mutable variables to control the flow and manipulate data
-fn main() {
-    let args: Vec<String> = std::env::args().collect();
+//
+// The exit code is 0 if anything was found, 1 if nothing was found and 2 on errors.
+fn main() -> ExitCode {
+    let options = match Options::parse_with_config(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("ERROR: {}", error);
+            return ExitCode::from(2);
+        }
+    };
+    init_logger(options.verbosity);
+
+    let result = match (&options.command, &options.files_from) {
+        (Command::Tokenize { input }, _) => tokenize_input(input, &options),
+        (Command::Clear, _) => clear_index(&options),
+        (_, Some(manifest)) => run(|| files_from(manifest), &options),
+        _ if is_document(&options.directory) => {
+            run(|| Ok(vec![options.directory.clone()]), &options)
+        }
+        _ => run(
+            || {
+                search_filetype(
+                    &options.directory,
+                    &options.filetype,
+                    options.symlinks,
+                    options.include_hidden,
+                )
+            },
+            &options,
+        ),
+    };
+
+    match result {
+        Ok(0) => ExitCode::from(1),
+        Ok(_) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("ERROR: {}", error);
+            ExitCode::from(2)
+        }
+    }
+}
+
+/// Represents what happened during an indexing run.
+///
+/// # Fields
+///
+/// * `indexed` - The number of files indexed.
+/// * `skipped` - The files that were skipped, with the reason why.
+/// * `terms` - The number of distinct terms in the index.
+/// * `elapsed` - How long the indexing took, including saving the index.
+/// * `index_size` - The size of the saved index file in bytes.
+struct IndexSummary {
+    indexed: usize,
+    skipped: Vec<(PathBuf, String)>,
+    terms: usize,
+    elapsed: Duration,
+    index_size: u64,
+}
+
+impl IndexSummary {
+    /// Logs the summary.
+    fn log(&self) {
+        info!(
+            "Indexed {} files ({} skipped), {} terms in {:.2?}, index size {} bytes",
+            self.indexed,
+            self.skipped.len(),
+            self.terms,
+            self.elapsed,
+            self.index_size
+        );
+        for (path, reason) in &self.skipped {
+            info!("    skipped {:?}: {}", path, reason);
+        }
+    }
+}
+
+/// Indexes the given files, saves the index and prints a summary of the run.
+///
+/// Documents that are already indexed, such as those of a resumed partial index, are kept as they
+/// are and only the given files are added to them. When `options.error_report` is set, every
+/// skipped file is written to it, see `write_error_report`. The caller holds the exclusive lock of
+/// the index, so the index and its checkpoints are saved without locking it again.
+///
+/// # Arguments
+///
+/// * `paths` - The paths of the files to index.
+/// * `skipped` - The files that were already skipped when listing the files, with the reason why.
+/// * `indexed` - The documents that are already indexed.
+/// * `json_name` - The path to save the index to.
+/// * `options` - The parsed command line `Options`.
+///
+/// # Returns
+///
+/// The `Index` of the documents.
+///
+/// # Errors
+///
+/// This function can return an `io::Error` if there is an issue saving the index or the error
+/// report.
+fn reindex(
+    paths: Vec<PathBuf>,
+    mut skipped: Vec<(PathBuf, String)>,
+    indexed: Vec<Document>,
+    json_name: &Path,
+    options: &Options,
+) -> Result<Index> {
+    if indexed.is_empty() {
+        info!("Reindexing data");
+    } else {
+        info!(
+            "Resuming interrupted indexing, {} files already indexed, {} to go",
+            indexed.len(),
+            paths.len()
+        );
+    }
+    let start = Instant::now();
+    let (data, unindexed) = tokenize_data(paths, indexed, options, Some(json_name));
+    skipped.extend(unindexed);
+    let settings = options.index_settings();
+    let index = Index::new(settings, data);
+    save_index(&index, json_name)?;
+
+    let summary = IndexSummary {
+        indexed: index.documents.len(),
+        skipped,
+        terms: index.term_count(),
+        elapsed: start.elapsed(),
+        index_size: fs::metadata(json_name)?.len(),
+    };
+    summary.log();
+    if let Some(report) = &options.error_report {
+        write_error_report(report, &summary.skipped)?;
+    }
+
+    Ok(index)
+}
+
+/// Writes the files skipped while indexing to a JSON report, for reviewing the gaps of an index.
+///
+/// The report is an array with an object for every skipped file, holding its `path` and the
+/// `reason` it was skipped, such as being unreadable, having no terms or being too large. An
+/// empty array means every file was indexed.
+///
+/// # Errors
+///
+/// This function can return an `io::Error` if the report can't be written.
+fn write_error_report(path: &Path, skipped: &[(PathBuf, String)]) -> Result<()> {
+    let report: Vec<serde_json::Value> = skipped
+        .iter()
+        .map(|(path, reason)| serde_json::json!({ "path": path, "reason": reason }))
+        .collect();
+
+    write_atomically(path, |writer| {
+        serde_json::to_writer_pretty(&mut *writer, &report)?;
+        writeln!(writer)
+    })?;
+    info!("Wrote the error report to {:?}", path);
+
+    Ok(())
+}
+
+/// Splits the files to index into the documents a partial index already holds and the files
+/// still to index.
+///
+/// A document of the partial index is kept if its file is still to be indexed and hasn't been
+/// modified since it was indexed. Documents without a known modification time are indexed again.
+///
+/// # Arguments
+///
+/// * `index` - The partial index.
+/// * `paths` - The paths of all the files to index.
+///
+/// # Returns
+///
+/// The documents to keep, and the paths of the files still to index.
+fn split_indexed(index: Index, paths: Vec<PathBuf>) -> (Vec<Document>, Vec<PathBuf>) {
+    let wanted: HashSet<&PathBuf> = paths.iter().collect();
+    let indexed: Vec<Document> = index
+        .documents
+        .into_iter()
+        .filter(|doc| {
+            let modified = fs::metadata(&doc.path)
+                .and_then(|meta| meta.modified())
+                .ok();
+            wanted.contains(&doc.path) && doc.modified.is_some() && doc.modified == modified
+        })
+        .collect();
+
+    let done: HashSet<&PathBuf> = indexed.iter().map(|doc| &doc.path).collect();
+    let remaining: Vec<PathBuf> = paths
+        .iter()
+        .filter(|path| !done.contains(path))
+        .cloned()
+        .collect();
+
+    (indexed, remaining)
+}
+
+/// Decides what happens to an index built with settings other than the current ones.
+///
+/// The settings are compared every time the index is loaded, and `--on-settings-change` chooses
+/// between reindexing, warning, asking the user and refusing the search. `--no-reindex` always
+/// refuses it.
+///
+/// # Arguments
+///
+/// * `index_settings` - The settings the index was built with.
+/// * `settings` - The settings of the current options.
+/// * `json_name` - The path of the index file.
+/// * `options` - The parsed command line `Options`.
+///
+/// # Returns
+///
+/// `true` if the index is searched as it is, `false` if it is reindexed.
+///
+/// # Errors
+///
+/// This function returns an `io::Error` if the search is refused, or if the answer of the user
+/// can't be read.
+fn keep_changed_settings(
+    index_settings: &IndexSettings,
+    settings: &IndexSettings,
+    json_name: &Path,
+    options: &Options,
+) -> Result<bool> {
+    let differences = index_settings.differences(settings).join(", ");
+    let refused_by = if options.no_reindex {
+        Some("--no-reindex is set")
+    } else if options.on_settings_change == SettingsChange::Error {
+        Some("--on-settings-change is error")
+    } else {
+        None
+    };
+    if let Some(refused_by) = refused_by {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Index {:?} was built with different settings ({}) and {}",
+                json_name, differences, refused_by
+            ),
+        ));
+    }
+
+    let keep = match options.on_settings_change {
+        SettingsChange::Reindex | SettingsChange::Error => false,
+        SettingsChange::Warn => true,
+        SettingsChange::Ask => {
+            // The prompt isn't output, so it goes to stderr like the other messages
+            eprint!(
+                "Index {:?} was built with different settings ({}). Reindex it? [Y/n] ",
+                json_name, differences
+            );
+            io::stderr().flush()?;
+
+            let mut answer = String::new();
+            io::stdin().read_line(&mut answer)?;
+            matches!(answer.trim().to_lowercase().as_str(), "n" | "no")
+        }
+    };
+    if keep {
+        warn!(
+            "Index was built with different settings ({}), results may be inconsistent",
+            differences
+        );
+    }
+    Ok(keep)
+}
+
+/// Deletes the index file, so the next search starts from a fresh index.
+///
+/// The index file is `options.index_path()`. Unless `options.yes` is set, the user is asked to
+/// confirm before it is deleted. A temporary file left behind by an interrupted save is deleted
+/// along with it.
+///
+/// # Arguments
+///
+/// * `options` - The parsed command line `Options`, holding the index path.
+///
+/// # Returns
+///
+/// 1 if the index was deleted, 0 if there was no index or the user didn't confirm.
+///
+/// # Errors
+///
+/// This function can return an `io::Error` if there is an issue reading the confirmation or
+/// deleting the file.
+fn clear_index(options: &Options) -> Result<usize> {
+    let json_name = options.index_path();
+    if !json_name.exists() {
+        info!("No index at {:?}", json_name);
+        return Ok(0);
+    }
+
+    if !options.yes {
+        // The prompt isn't output, so it goes to stderr like the other messages
+        eprint!("Delete the index {:?}? [y/N] ", json_name);
+        io::stderr().flush()?;
+
+        let mut answer = String::new();
+        io::stdin().read_line(&mut answer)?;
+        if !matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+            info!("Index kept");
+            return Ok(0);
+        }
+    }
+
+    let lock = lock_index(&json_name, true);
+    fs::remove_file(&json_name)?;
+    let mut temp_name = json_name.clone().into_os_string();
+    temp_name.push(".tmp");
+    let _ = fs::remove_file(temp_name);
+    // The lock file is kept: a run waiting for the lock would otherwise lock the deleted file,
+    // while the next run locks a new one, and both would write the index at the same time
+    drop(lock);
+
+    info!("Deleted {:?}", json_name);
+    Ok(1)
+}
+
+/// Prints the tokens of a file or a string, one per line.
+///
+/// This function is meant for debugging why a query doesn't match: it shows exactly which terms
+/// the `Tokenizer` from the options produces, without indexing anything. If `input` is the path of
+/// an existing file or a URL, the document is read with `read_document`.
+/// Otherwise `input` itself is tokenized, which shows how a query is tokenized.
+///
+/// # Arguments
+///
+/// * `input` - The path of a file, or a string.
+/// * `options` - The parsed command line `Options`, holding the tokenizer and read options.
+///
+/// # Returns
+///
+/// The number of tokens.
+///
+/// # Errors
+///
+/// This function can return an `io::Error` if there is an issue reading the file.
+fn tokenize_input(input: &str, options: &Options) -> Result<usize> {
+    let path = PathBuf::from(input);
+    let text = if is_document(&path) {
+        read_document(&path, &options.read_options)?.content
+    } else {
+        input.to_string()
+    };
+
+    let tokens = options.tokenizer.tokenize(&text);
+    let mut out = io::stdout().lock();
+    for token in &tokens {
+        writeln!(out, "{}", token)?;
+    }
+
+    Ok(tokens.len())
+}
+
+/// Removes the index file itself, and the temporary and lock files written next to it, from the
+/// files to index.
+///
+/// When JSON files are indexed, the index could otherwise be tokenized as a document of its own
+/// index, which feeds every indexed term back into the next index. This holds whatever the
+/// filetype, and also for an index at a custom `--index-path` inside the directory, hidden files
+/// included with `--include-hidden` and files listed with `--files-from`. Paths are compared after
+/// canonicalizing them, so the index is recognized under any spelling of its path.
+///
+/// # Arguments
+///
+/// * `paths` - The paths of the files to index.
+/// * `json_name` - The path of the index file.
+///
+/// # Returns
+///
+/// The paths without the index files.
+fn exclude_index_files(paths: Vec<PathBuf>, json_name: &Path) -> Vec<PathBuf> {
+    // The index doesn't exist before it is first saved, but its directory does
+    let canonical = |path: &Path| -> PathBuf {
+        fs::canonicalize(path).unwrap_or_else(|_| match (path.parent(), path.file_name()) {
+            (Some(parent), Some(name)) => fs::canonicalize(match parent.as_os_str().is_empty() {
+                true => Path::new("."),
+                false => parent,
+            })
+            .map(|parent| parent.join(name))
+            .unwrap_or_else(|_| path.to_path_buf()),
+            _ => path.to_path_buf(),
+        })
+    };
+    let mut temp_name = json_name.as_os_str().to_os_string();
+    temp_name.push(".tmp");
+    let index_files: HashSet<PathBuf> = [
+        Some(json_name.to_path_buf()),
+        Some(temp_name.into()),
+        lock_path(json_name),
+    ]
+    .into_iter()
+    .flatten()
+    .map(|path| canonical(&path))
+    .collect();
+
+    paths
+        .into_iter()
+        .filter(|path| {
+            let excluded = index_files.contains(&canonical(path));
+            if excluded {
+                debug!("Not indexing the index file {:?}", path);
+            }
+            !excluded
+        })
+        .collect()
+}
 
-    if args.len() <= 2 {
-        panic!("ERROR: Enter filetype, directory, word")
+/// Removes the files that are listed more than once from the files to index.
+///
+/// Overlapping directories or manifests can list the same file twice, possibly under different
+/// paths such as `books/a.pdf` and `./books/../books/a.pdf`. Such a file would be indexed twice,
+/// show up twice in the results and count twice in the document frequencies. Paths are compared
+/// after canonicalizing them, and the last occurrence of a file is kept.
+///
+/// # Arguments
+///
+/// * `paths` - The paths of the files to index.
+/// * `duplicates` - How a file that is listed more than once is handled.
+///
+/// # Errors
+///
+/// This function returns an `io::Error` if a file is listed more than once and `duplicates` is
+/// `Duplicates::Error`.
+fn dedupe_paths(paths: Vec<PathBuf>, duplicates: Duplicates) -> Result<Vec<PathBuf>> {
+    let mut seen: HashSet<PathBuf> = HashSet::new();
+    let mut unique: Vec<PathBuf> = Vec::with_capacity(paths.len());
+
+    for path in paths.into_iter().rev() {
+        let canonical = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+        if seen.insert(canonical) {
+            unique.push(path);
+        } else if duplicates == Duplicates::Error {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{:?} is listed more than once", path),
+            ));
+        } else {
+            warn!("{:?} is listed more than once, only indexing it once", path);
+        }
+    }
+    unique.reverse();
+
+    Ok(unique)
+}
+
+/// Removes the search results that are the same file as a better ranked result.
+///
+/// A file reachable by several paths, such as through a symbolic link or from manifests using
+/// relative and absolute paths, can be indexed under each of them and show up once per path. The
+/// paths are compared after canonicalizing them, like in `dedupe_paths`, but the first, best
+/// ranked occurrence is kept. URLs and files that no longer exist are compared as they are.
+///
+/// # Arguments
+///
+/// * `results` - The ranked results.
+///
+/// # Returns
+///
+/// The results without the duplicates, in the same order.
+fn dedupe_results(results: Vec<Idf>) -> Vec<Idf> {
+    let mut seen: HashSet<PathBuf> = HashSet::new();
+
+    results
+        .into_iter()
+        .filter(|elem| {
+            let canonical = match is_url(&elem.path) {
+                true => elem.path.clone(),
+                false => fs::canonicalize(&elem.path).unwrap_or_else(|_| elem.path.clone()),
+            };
+            let first = seen.insert(canonical);
+            if !first {
+                debug!("{:?} is the same file as a better ranked result", elem.path);
+            }
+            first
+        })
+        .collect()
+}
+
+/// Lists the files that would be indexed, without reading or tokenizing them.
+///
+/// The files to index are printed to stdout, one per line. The files of the directory that
+/// don't match the filetype, and symbolic links unless they are followed, are logged as skipped, while the files of a `--files-from` manifest
+/// that don't exist, duplicate files and files that are empty, unreadable or too large are already
+/// warned about when the files are listed.
+///
+/// # Arguments
+///
+/// * `paths` - The files that would be indexed, after removing duplicates.
+/// * `skipped` - The files that were skipped when listing the files, with the reason why.
+/// * `options` - The parsed command line `Options`, holding the directory and filetype.
+///
+/// # Returns
+///
+/// The number of files that would be indexed.
+///
+/// # Errors
+///
+/// This function can return an `io::Error` if the directory can't be read or stdout can't be
+/// written to.
+fn dry_run(
+    paths: Vec<PathBuf>,
+    skipped: Vec<(PathBuf, String)>,
+    options: &Options,
+) -> Result<usize> {
+    let wanted: HashSet<&PathBuf> = paths
+        .iter()
+        .chain(skipped.iter().map(|(path, _)| path))
+        .collect();
+    let index_path = options.index_path();
+    let lock_path = lock_path(&index_path);
+    let mut skipped = skipped.len();
+
+    // Only the files of a directory are filtered by their filetype
+    if options.files_from.is_none() && !is_document(&options.directory) {
+        for entry in fs::read_dir(&options.directory)? {
+            let path = entry?.path();
+            if !path.is_file()
+                || path == index_path
+                || Some(&path) == lock_path.as_ref()
+                || wanted.contains(&path)
+            {
+                continue;
+            }
+            if path.is_symlink() {
+                // A followed link is listed under the path of its target instead
+                if options.symlinks == Symlinks::Skip {
+                    info!("Would skip {:?}: symbolic link", path);
+                    skipped += 1;
+                }
+                continue;
+            }
+            let hidden = path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+            if hidden && !options.include_hidden {
+                info!("Would skip {:?}: hidden file", path);
+                skipped += 1;
+                continue;
+            }
+            info!(
+                "Would skip {:?}: doesn't match the filetype {:?}",
+                path, options.filetype
+            );
+            skipped += 1;
+        }
     }
 
-    let filetype = args.get(1).expect("ERROR: Enter a filetype").to_string();
-    let directory = args.get(2).expect("ERROR: Enter a directory").to_string();
-    let query = args.get(3).expect("ERROR: Enter a query").to_string();
-    let all_pdfs_paths = search_filetype(&directory, &filetype).expect("Couln't find pdfs");
+    let mut out = io::stdout().lock();
+    for path in &paths {
+        writeln!(out, "{}", path.display())?;
+    }
+    info!(
+        "{} files would be indexed, {} skipped",
+        paths.len(),
+        skipped
+    );
 
-    run(directory, all_pdfs_paths, query).expect("Couldn't run main");
+    Ok(paths.len())
+}
+
+/// Checks whether the index is still in sync with the files, without modifying anything.
+///
+/// Every issue is printed to stdout, one per line:
+///
+/// * `missing` - An indexed document whose file no longer exists.
+/// * `stale` - An indexed document whose file was modified since it was indexed.
+/// * `new` - A file to index that isn't in the index.
+/// * `settings` - The index was built with settings that differ from the current options.
+/// * `partial` - The index is a checkpoint of an indexing run that was interrupted.
+/// * `outdated` - The index was saved by an older version and can't be read.
+///
+/// Documents given by URL aren't checked. A summary is logged at the end.
+///
+/// # Arguments
+///
+/// * `paths` - The files that would be indexed.
+/// * `options` - The parsed command line `Options`, holding the index path and settings.
+///
+/// # Returns
+///
+/// 1 if the index is in sync and 0 if issues were found, so the exit code is 1 when the index
+/// needs to be reindexed.
+///
+/// # Errors
+///
+/// This function can return an `io::Error` if there is no index, or stdout can't be written to.
+fn verify(paths: Vec<PathBuf>, options: &Options) -> Result<usize> {
+    let json_name = options.index_path();
+    let mut out = io::stdout().lock();
+
+    if !json_name.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("No index at {:?}", json_name),
+        ));
+    }
+    let index = match load_index(&json_name) {
+        Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+            writeln!(out, "outdated: {:?}", json_name)?;
+            info!("Index {:?} needs to be reindexed", json_name);
+            return Ok(0);
+        }
+        result => result?,
+    };
+
+    let mut issues: Vec<(&str, String)> = Vec::new();
+    let differences = index.settings.differences(&options.index_settings());
+    if !differences.is_empty() {
+        issues.push(("settings", differences.join(", ")));
+    }
+    if index.partial {
+        issues.push(("partial", format!("{:?}", json_name)));
+    }
+
+    let (mut missing, mut stale) = (0, 0);
+    for doc in index.documents.iter().filter(|doc| !is_url(&doc.path)) {
+        match fs::metadata(&doc.path).and_then(|meta| meta.modified()) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                issues.push(("missing", format!("{:?}", doc.path)));
+                missing += 1;
+            }
+            // Documents indexed without a modification time are compared to the indexing time
+            Ok(modified)
+                if doc
+                    .modified
+                    .map_or(modified > doc.last_modified, |indexed| indexed != modified) =>
+            {
+                issues.push(("stale", format!("{:?}", doc.path)));
+                stale += 1;
+            }
+            _ => {}
+        }
+    }
+
+    let indexed: HashSet<&PathBuf> = index.documents.iter().map(|doc| &doc.path).collect();
+    let new: Vec<&PathBuf> = paths
+        .iter()
+        .filter(|path| !indexed.contains(path))
+        .collect();
+    for path in &new {
+        issues.push(("new", format!("{:?}", path)));
+    }
+
+    for (kind, detail) in &issues {
+        writeln!(out, "{}: {}", kind, detail)?;
+    }
+    info!(
+        "Checked {} documents: {} missing, {} stale, {} new",
+        index.documents.len(),
+        missing,
+        stale,
+        new.len()
+    );
+
+    if issues.is_empty() {
+        info!("Index is up to date");
+        Ok(1)
+    } else {
+        info!("Index needs to be reindexed");
+        Ok(0)
+    }
+}
+
+/// Checks the files to index before they are read, leaving out those that can't be indexed with
+/// a warning for each.
+///
+/// Empty files, and files that can't be opened, for example for lack of permission, are left out
+/// unless `options.no_precheck` is set, so they don't fail one by one while indexing. Files larger
+/// than `options.max_file_size` are always left out. URLs aren't checked.
+///
+/// # Returns
+///
+/// The files to keep, and the files that were left out with the reason why.
+fn check_files(paths: Vec<PathBuf>, options: &Options) -> (Vec<PathBuf>, Vec<(PathBuf, String)>) {
+    let mut kept: Vec<PathBuf> = Vec::with_capacity(paths.len());
+    let mut skipped: Vec<(PathBuf, String)> = Vec::new();
+
+    for path in paths {
+        match check_file(&path, options) {
+            Some(reason) => {
+                warn!("Skipping {:?}: {}", path, reason);
+                skipped.push((path, reason));
+            }
+            None => kept.push(path),
+        }
+    }
+
+    (kept, skipped)
+}
+
+/// Returns why a file can't be indexed, or `None` if it can. See `check_files`.
+fn check_file(path: &Path, options: &Options) -> Option<String> {
+    if is_url(path) {
+        return None;
+    }
+    let meta = match fs::metadata(path) {
+        Ok(meta) => meta,
+        Err(e) if !options.no_precheck => return Some(format!("unreadable: {}", e)),
+        Err(_) => return None,
+    };
+
+    match options.max_file_size {
+        Some(max_size) if meta.len() > max_size => Some(format!(
+            "too large: {} bytes, above --max-file-size {}",
+            meta.len(),
+            max_size
+        )),
+        _ if options.no_precheck => None,
+        _ if meta.len() == 0 => Some("empty".to_string()),
+        _ => fs::File::open(path)
+            .err()
+            .map(|e| format!("unreadable: {}", e)),
+    }
+}
+
+/// Returns whether the path is a single document, an existing file or an `http://` or
+/// `https://` URL, as opposed to a directory to search.
+fn is_document(path: &Path) -> bool {
+    path.is_file() || is_url(path)
+}
+
+/// Reads the files to index from a `--files-from` manifest with `read_file_list`.
+///
+/// Listed paths that aren't existing files or URLs are skipped with a warning.
+///
+/// # Errors
+///
+/// This function can return an `io::Error` if the manifest can't be read.
+fn files_from(manifest: &Path) -> Result<Vec<PathBuf>> {
+    let (found, missing): (Vec<PathBuf>, Vec<PathBuf>) = read_file_list(manifest)?
+        .into_iter()
+        .partition(|path| is_document(path));
+
+    for path in missing {
+        warn!("{:?} listed in {:?} doesn't exist", path, manifest);
+    }
+
+    Ok(found)
 }
 
 /// Tokenizes the content of PDF files and creates a vector of Document structs.
 ///
 /// This function takes a vector of file paths (`Vec<PathBuf>`) representing PDF files. It reads
-/// the content of each file using the `read_from_pdf` function, tokenizes the content into
-/// individual words using the `split_into_words` function, and creates a Document struct for each
-/// file. The Document structs contain the tokenized data, file path, and the current system time
-/// as the last modified timestamp.
+/// the content of each file using the `read_document` function, tokenizes the content into
+/// individual words using the `Tokenizer` from the options, and creates a Document struct for each
+/// file. The title from the PDF metadata, if any, is tokenized separately from the body. The
+/// Document structs contain the tokenized data, file path, and the current system time as the last
+/// modified timestamp. When `options.store_tokens` is set, the ordered token list is kept on the
+/// Document as well so snippets can be served from the index alone, and when
+/// `options.store_raw_words` is set, the distinct words as written are kept for the exact-match
+/// boost. When `options.store_pages`
+/// is set, the pages every term occurs on are kept for documents with pages. Files that can't be read, or
+/// that contain no terms, are skipped. When `options.index_paths` is
+/// set, the terms of the file path are merged into the term frequencies, weighted by
+/// `options.path_boost`, so a query for part of a filename surfaces the document.
+///
+/// The new documents are added to the `indexed` documents, which are already indexed. When
+/// `checkpoint` is given and `options.checkpoint_every` is set, all the documents indexed so far
+/// are saved to `checkpoint` as a partial index after every that many documents, so the work
+/// isn't lost if a long indexing run is interrupted. The checkpoints are saved in the background
+/// unless `options.sync_checkpoints` is set, see `CheckpointSaver`, and the last one has been
+/// written when this function returns.
 ///
 /// # Arguments
 ///
 /// * `paths` - A vector of file paths (`Vec<PathBuf>`) representing the PDF files to tokenize.
+/// * `indexed` - The documents that are already indexed, to add the new documents to.
+/// * `options` - The parsed command line `Options`, holding the indexing flags.
+/// * `checkpoint` - The path of the index to save checkpoints to, if any.
 ///
 /// # Returns
 ///
-/// A vector of Document structs representing the tokenized data from the PDF files.
+/// A vector of Document structs representing the tokenized data from the PDF files, and the paths
+/// of the skipped files with the reason why they were skipped.
 ///
 /// # Examples
 ///
@@ -120,93 +1296,422 @@ fn main() {
 ///     PathBuf::from("file3.pdf"),
 /// ];
 ///
-/// let documents = tokenize_data(paths);
+/// let (documents, skipped) = tokenize_data(paths, Vec::new(), &Options::default(), None);
 ///
 /// assert_eq!(documents.len(), 3);
 /// // Check the contents of the first document
 /// assert_eq!(documents[0].path, PathBuf::from("file1.pdf"));
 /// assert!(documents[0].last_modified.elapsed().is_ok());
 /// ```
-fn tokenize_data(paths: Vec<PathBuf>) -> Vec<Document> {
-    let mut documents: Vec<Document> = Vec::new();
+fn tokenize_data(
+    paths: Vec<PathBuf>,
+    indexed: Vec<Document>,
+    options: &Options,
+    checkpoint: Option<&Path>,
+) -> (Vec<Document>, Vec<(PathBuf, String)>) {
+    let mut documents: Vec<Document> = indexed;
+    let mut skipped: Vec<(PathBuf, String)> = Vec::new();
+    let mut saver = CheckpointSaver::default();
 
     for path in paths {
-        let content = read_from_pdf(&path);
-        let data = split_into_words(&content);
-        let tsk = index_data(data);
+        let text = match read_document(&path, &options.read_options) {
+            Ok(text) => text,
+            Err(error) => {
+                skipped.push((path, format!("unreadable: {}", error)));
+                continue;
+            }
+        };
+        if text.invalid_chars > 0 {
+            warn!(
+                "{:?} contains {} characters that weren't valid UTF-8{}",
+                path,
+                text.invalid_chars,
+                if options.read_options.strip_invalid {
+                    ", stripped"
+                } else {
+                    ", use --strip-invalid to strip them"
+                }
+            );
+        }
+        let data = options.tokenizer.tokenize(&text.content);
+        if data.is_empty() {
+            skipped.push((path, "no terms".to_string()));
+            continue;
+        }
+        let tokens = if options.store_tokens {
+            Some(data.clone())
+        } else {
+            None
+        };
+        let raw_words = if options.store_raw_words {
+            Some(raw_words(&text.content))
+        } else {
+            None
+        };
+        let len = data.len();
+        // The title is weighed like the body, so a short title doesn't outweigh a long body
+        let title_data = text
+            .title
+            .as_deref()
+            .map(|title| index_field_data(options.tokenizer.tokenize(title), &data, options.tf_cap))
+            .unwrap_or_default();
+        let mut tsk = index_data(data, options.tf_cap);
+        if options.index_paths {
+            for (term, freq) in index_data(path_terms(&path, &options.tokenizer), options.tf_cap) {
+                *tsk.entry(term).or_insert(0.0) += options.path_boost * freq;
+            }
+        }
+        // The title and body are searched as fields of their own
+        let fields = text
+            .fields
+            .iter()
+            .filter(|(name, _)| name != "title" && name != "body")
+            .map(|(name, value)| {
+                let term_freq = index_data(options.tokenizer.tokenize(value), options.tf_cap);
+                (name.clone(), term_freq)
+            })
+            .filter(|(_, term_freq)| !term_freq.is_empty())
+            .collect();
+        let pages = if options.store_pages && !text.page_starts.is_empty() {
+            Some(index_pages(text.pages(), &options.tokenizer))
+        } else {
+            None
+        };
+
+        let modified = fs::metadata(&path).and_then(|meta| meta.modified()).ok();
 
         let document = Document {
             data: DocFreq::single(path.clone(), tsk),
             path,
             last_modified: SystemTime::now(),
+            modified,
+            tokens,
+            title: text.title,
+            title_data,
+            pages,
+            len,
+            fields,
+            raw_words,
         };
+        debug!("Indexed {:?}, {} terms", document.path, document.len);
         documents.push(document);
+
+        if let Some(checkpoint) = checkpoint {
+            if options.checkpoint_every > 0
+                && documents.len().is_multiple_of(options.checkpoint_every)
+            {
+                saver.save(&mut documents, checkpoint, options);
+            }
+        }
     }
 
-    documents
+    // The index is saved to the same path afterwards, which the checkpoint mustn't overwrite
+    saver.finish();
+    (documents, skipped)
 }
 
-/// Searches for the given query in the provided documents and prints the search results.
+/// Saves checkpoints on a background thread, so indexing continues while they are written.
 ///
-/// This function takes a vector of `Document` structs and a query string as input. It performs a
-/// search by calculating the inverse document frequency (IDF) for each document and query term
-/// combination. The search results are then printed to the console.
+/// The documents are cloned as a snapshot for the writer thread, while indexing goes on adding to
+/// the original. At most one checkpoint is written at a time: when the next checkpoint comes due
+/// while the previous one is still being written, it is skipped rather than queued, so a slow disk
+/// neither stalls indexing nor piles up snapshots in memory.
 ///
-/// # Arguments
+/// # Fields
 ///
-/// * `docs` - A vector of `Document` structs representing the documents to search.
-/// * `query` - A string representing the query to search for.
+/// * `pending` - The thread writing the last checkpoint, if any.
+#[derive(Default)]
+struct CheckpointSaver {
+    pending: Option<JoinHandle<()>>,
+}
+
+impl CheckpointSaver {
+    /// Saves the documents indexed so far as a partial index to `path`.
+    ///
+    /// With `options.sync_checkpoints` set, the checkpoint is saved before this function returns,
+    /// otherwise on a background thread.
+    ///
+    /// # Arguments
+    ///
+    /// * `documents` - The documents indexed so far.
+    /// * `path` - The path of the index to save the checkpoint to.
+    /// * `options` - The parsed command line `Options`, holding the indexing flags.
+    fn save(&mut self, documents: &mut Vec<Document>, path: &Path, options: &Options) {
+        if options.sync_checkpoints {
+            save_checkpoint(documents, path, options);
+            return;
+        }
+        if self
+            .pending
+            .as_ref()
+            .is_some_and(|pending| !pending.is_finished())
+        {
+            debug!(
+                "Skipping the checkpoint at {} documents, the previous one is still being saved",
+                documents.len()
+            );
+            return;
+        }
+        self.finish();
+
+        let snapshot = documents.clone();
+        let settings = options.index_settings();
+        let path = path.to_path_buf();
+        self.pending = Some(thread::spawn(move || {
+            let mut index = Index::new(settings, snapshot);
+            index.partial = true;
+            if let Err(error) = save_index(&index, &path) {
+                warn!("Couldn't save a checkpoint to {:?}: {}", path, error);
+            }
+        }));
+    }
+
+    /// Waits until the checkpoint being saved, if any, has been written.
+    fn finish(&mut self) {
+        if let Some(pending) = self.pending.take() {
+            if pending.join().is_err() {
+                warn!("Saving a checkpoint failed unexpectedly");
+            }
+        }
+    }
+}
+
+/// Saves the documents indexed so far as a partial index.
 ///
-/// # Examples
+/// The documents are moved into the index for saving and back out again, so they aren't copied.
+/// A checkpoint that can't be saved only gets a warning, since indexing can still finish.
+fn save_checkpoint(documents: &mut Vec<Document>, path: &Path, options: &Options) {
+    let mut index = Index::new(options.index_settings(), std::mem::take(documents));
+    index.partial = true;
+
+    if let Err(error) = save_index(&index, path) {
+        warn!("Couldn't save a checkpoint to {:?}: {}", path, error);
+    }
+    *documents = index.documents;
+}
+
+/// Writes the ranked search results, one per line.
 ///
-/// ```
-/// use std::path::PathBuf;
+/// Only the results are written to `out`. If there are none, "No matches found" is logged
+/// instead, so an empty result set leaves the output empty.
 ///
-/// let doc1 = Document {
-///     data: DocFreq::default(),
-///     path: PathBuf::from("file1.pdf"),
-///     last_modified: SystemTime::now(),
-/// };
-/// let doc2 = Document {
-///     data: DocFreq::default(),
-///     path: PathBuf::from("file2.pdf"),
-///     last_modified: SystemTime::now(),
-/// };
+/// When `options.group_by_dir` is set, the results are grouped by their parent directory with
+/// `group_by_dir`, and every group is written under a header with the directory and its number
+/// of results. Results keep their overall rank within a group.
 ///
-/// let docs = vec![doc1, doc2];
+/// Only the page of results selected by `options.offset` and `options.limit` is written, after
+/// skipping `options.offset` results. The results keep their overall rank.
 ///
-/// search_query(docs, "example".to_string());
-/// ```
+/// When `options.normalize_scores` is set, scores are written as a percentage of the best score,
+/// so the best match is 100% and the others scale relative to it.
+///
+/// When `options.compare_scorers` is set, the score of every result under each of the built-in
+/// ranking formulas is written under it, whichever formula ranked the results.
+///
+/// When `options.explain` is set, the per-term breakdown of the score (tf, df, idf and the
+/// contribution to the score) is written under each result.
+///
+/// When `options.snippets` is set, a snippet of context around the query is written under each
+/// result, chosen by `options.snippet_strategy`: by default the passage where the query terms
+/// weigh the most by their IDF, otherwise the passage around the first query term found. The
+/// snippet is taken from the stored tokens of the document if present, otherwise the source file
+/// is read and tokenized again.
+///
+/// # Arguments
+///
+/// * `out` - The writer to write the results to.
+/// * `results` - The ranked results, as returned by `Index::search`.
+/// * `docs` - The documents that were searched.
+/// * `query` - The query that was searched for.
+/// * `options` - The parsed command line `Options`, holding the grouping, score normalization,
+///   explain, snippets and pagination flags.
 ///
-/// The function can be used with any valid vector of `Document` structs and a query string to
-/// search for the query in the documents and print the search results.
-pub fn search_query(docs: Vec<Document>, query: String) {
-    let mut idf_buff: Vec<Idf> = Vec::new();
-
-    for doc in &docs {
-        let tf = doc
-            .data
-            .get(&doc.path)
-            .and_then(|term_freq| term_freq.get(&query))
-            .cloned()
-            .unwrap_or(0.0);
-
-        let idf = Idf {
-            path: doc.path.clone(),
-            tf,
+/// # Errors
+///
+/// This function can return an `io::Error` if there is an issue writing to `out`.
+fn write_results(
+    out: &mut impl Write,
+    results: &[Idf],
+    docs: &[Document],
+    query: &str,
+    options: &Options,
+) -> Result<()> {
+    let terms: Vec<String> = parse_query(query, &options.tokenizer, docs)
+        .into_iter()
+        .map(|term| term.term)
+        .collect();
+
+    if results.is_empty() {
+        info!("No matches found");
+        return Ok(());
+    }
+    // The best score is the largest, whatever the order of the results
+    let top = results.iter().map(|elem| elem.tf).fold(0.0, f32::max);
+
+    let end = match options.limit {
+        Some(limit) => options.offset.saturating_add(limit).min(results.len()),
+        None => results.len(),
+    };
+    let start = options.offset.min(end);
+    if start >= end {
+        info!(
+            "No results past --offset {}, only {} found",
+            options.offset,
+            results.len()
+        );
+        return Ok(());
+    }
+    if start > 0 || end < results.len() {
+        info!(
+            "Showing results {} to {} of {}",
+            start + 1,
+            end,
+            results.len()
+        );
+    }
+    let page = &results[start..end];
+
+    if !options.group_by_dir {
+        for (idx, elem) in page.iter().enumerate() {
+            write_result(out, start + idx + 1, elem, top, docs, &terms, options)?;
+        }
+        return Ok(());
+    }
+
+    for (idx, (dir, group)) in group_by_dir(page, start + 1).into_iter().enumerate() {
+        if idx > 0 {
+            writeln!(out)?;
+        }
+        writeln!(out, "{:?} ({} results)", dir, group.len())?;
+
+        for (rank, elem) in group {
+            write_result(out, rank, elem, top, docs, &terms, options)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes a single search result with its rank, followed by its explanation and snippet if
+/// requested in `options`. `top` is the best score of all results, which normalized scores are
+/// relative to.
+///
+/// # Errors
+///
+/// This function can return an `io::Error` if there is an issue writing to `out`.
+fn write_result(
+    out: &mut impl Write,
+    rank: usize,
+    elem: &Idf,
+    top: f32,
+    docs: &[Document],
+    terms: &[String],
+    options: &Options,
+) -> Result<()> {
+    let score_pct = if top > 0.0 {
+        elem.tf / top * 100.0
+    } else {
+        0.0
+    };
+    if let Some(template) = &options.template {
+        writeln!(
+            out,
+            "{}",
+            template.render(rank, &elem.path, elem.tf, score_pct)
+        )?;
+    } else if options.normalize_scores && top > 0.0 {
+        writeln!(out, "{}: {:?}, {:.0}%", rank, elem.path, score_pct)?;
+    } else {
+        writeln!(out, "{}: {:?}, {}", rank, elem.path, elem.tf)?;
+    }
+
+    if !elem.pages.is_empty() {
+        let pages: Vec<String> = elem.pages.iter().map(usize::to_string).collect();
+        writeln!(out, "    pages {}", pages.join(", "))?;
+    }
+
+    if let Some(comparison) = &elem.comparison {
+        writeln!(
+            out,
+            "    tf={} tfidf={} bm25={}",
+            comparison.tf, comparison.tf_idf, comparison.bm25
+        )?;
+    }
+
+    if options.explain {
+        for term in &elem.explanation {
+            writeln!(
+                out,
+                "    {}: tf={} title_tf={} df={} idf={} contribution={}",
+                term.term, term.tf, term.title_tf, term.df, term.idf, term.contribution
+            )?;
+        }
+    }
+
+    if options.snippets {
+        let doc = docs.iter().find(|doc| doc.path == elem.path);
+        let tokens = match doc.and_then(|doc| doc.tokens.clone()) {
+            Some(tokens) => tokens,
+            None => options.tokenizer.tokenize(
+                &read_document(&elem.path, &options.read_options)
+                    .map(|text| text.content)
+                    .unwrap_or_default(),
+            ),
+        };
+        let text = match options.snippet_strategy {
+            SnippetStrategy::Best => {
+                // Terms missing from the explanation, like scoped ones, still count
+                let weights: HashMap<String, f32> = terms
+                    .iter()
+                    .map(|term| {
+                        let idf = elem
+                            .explanation
+                            .iter()
+                            .find(|score| &score.term == term)
+                            .map_or(1.0, |score| score.idf);
+                        (term.clone(), idf)
+                    })
+                    .collect();
+                best_snippet(&tokens, &weights, SNIPPET_RADIUS)
+            }
+            SnippetStrategy::First => terms
+                .iter()
+                .find_map(|term| snippet(&tokens, term, SNIPPET_RADIUS)),
         };
 
-        if !idf_buff.contains(&idf) {
-            idf_buff.push(idf);
+        if let Some(text) = text {
+            writeln!(out, "    ...{}...", text)?;
         }
     }
 
-    idf_buff.sort_by(|a, b| {
-        b.tf.partial_cmp(&a.tf)
-            .expect("Unable to compare arguments")
-    });
+    Ok(())
+}
+
+/// Groups ranked search results by the parent directory of their path.
+///
+/// The groups are ordered by their best result, so the directory with the most relevant hit
+/// comes first, and the results within a group keep their ranked order. Every result is paired
+/// with its overall rank, starting at `first_rank`.
+///
+/// # Arguments
+///
+/// * `results` - The ranked results, as returned by `Index::search`.
+/// * `first_rank` - The rank of the first result, which is 1 unless the results are a later page.
+///
+/// # Returns
+///
+/// A vector of the directories and their ranked results.
+fn group_by_dir(results: &[Idf], first_rank: usize) -> Vec<(PathBuf, Vec<(usize, &Idf)>)> {
+    let mut groups: Vec<(PathBuf, Vec<(usize, &Idf)>)> = Vec::new();
+
+    for (idx, elem) in results.iter().enumerate() {
+        let dir = elem.path.parent().unwrap_or(Path::new("")).to_path_buf();
 
-    for (idx, elem) in idf_buff.into_iter().enumerate() {
-        println!("{}: {:?}, {}", idx + 1, elem.path, elem.tf);
+        match groups.iter_mut().find(|(seen, _)| *seen == dir) {
+            Some((_, group)) => group.push((first_rank + idx, elem)),
+            None => groups.push((dir, vec![(first_rank + idx, elem)])),
+        }
     }
+
+    groups
 }
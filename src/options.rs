@@ -0,0 +1,1035 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tf_idf::lexer::{
+    index::{
+        IndexSettings, Operator, Order, RepeatedTerms, SearchOpts, Tiebreak, DEFAULT_TITLE_BOOST,
+        INDEX_VERSION,
+    },
+    lexing::{SnippetStrategy, Stopwords, TfCap, Tokenizer, ENGLISH_STOPWORDS},
+    lib::{read_boosts, ReadOptions, Symlinks},
+    scoring::{IdfVariant, QueryNorm, Ranking},
+};
+
+/// The file name of the index inside the searched directory.
+pub const INDEX_FILE_NAME: &str = ".data.json";
+
+/// The file name of the config file inside the `tf-idf` config directory.
+pub const CONFIG_FILE_NAME: &str = "config.toml";
+
+/// The default minimum length, in characters, of the longest query term.
+pub const DEFAULT_MIN_QUERY_LEN: usize = 1;
+
+/// The default number of highest weighted terms of the document `similar` compares.
+pub const DEFAULT_SIMILAR_TERMS: usize = 20;
+
+/// The default number of terms `keywords` and `cooccur` list.
+pub const DEFAULT_KEYWORD_COUNT: usize = 10;
+
+/// The default multiplier applied to the terms of a document's path.
+pub const DEFAULT_PATH_BOOST: f32 = 1.0;
+
+/// Represents the command to run.
+///
+/// # Variants
+///
+/// * `Search` - Search the index for the query. This is the default command.
+/// * `Index` - Only (re)index the directory and save the index. Used when the query is omitted.
+/// * `ExportMatrix` - Export the index as a term/document matrix in TSV format to `output`.
+/// * `ExportSimilarity` - Export the cosine similarity between the documents to `output`.
+/// * `Tokenize` - Print the tokens of `input`, which is a file or else a string, without indexing.
+/// * `Clear` - Delete the index file of the directory.
+/// * `Similar` - List the documents most similar to the document at `path`.
+/// * `Keywords` - List the most characteristic terms of the document at `path`.
+/// * `Cooccur` - List the terms that most frequently co-occur with `term`.
+/// * `Verify` - Check whether the index is still in sync with the files, without modifying it.
+/// * `Vocab` - List every term of the index with its total count and document frequency.
+#[derive(Debug, Default)]
+pub enum Command {
+    #[default]
+    Search,
+    Index,
+    ExportMatrix {
+        output: PathBuf,
+    },
+    ExportSimilarity {
+        output: PathBuf,
+    },
+    Tokenize {
+        input: String,
+    },
+    Clear,
+    Similar {
+        path: PathBuf,
+    },
+    Keywords {
+        path: PathBuf,
+    },
+    Cooccur {
+        term: String,
+    },
+    Verify,
+    Vocab,
+}
+
+/// Represents how much the tool logs besides the results.
+///
+/// # Variants
+///
+/// * `Quiet` - Only errors are logged, so only the results are printed.
+/// * `Normal` - Status messages and warnings are logged. This is the default.
+/// * `Verbose` - Debugging details, such as every indexed file and timings, are logged too.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Verbosity {
+    Quiet,
+    #[default]
+    Normal,
+    Verbose,
+}
+
+/// Represents how a file that is listed more than once for indexing is handled.
+///
+/// # Variants
+///
+/// * `Latest` - Only the last occurrence of the file is indexed, with a warning. This is the
+///   default.
+/// * `Error` - Indexing fails.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Duplicates {
+    #[default]
+    Latest,
+    Error,
+}
+
+impl FromStr for Duplicates {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "latest" => Ok(Duplicates::Latest),
+            "error" => Ok(Duplicates::Error),
+            _ => Err(format!("Unknown duplicates policy: {}", s)),
+        }
+    }
+}
+
+/// Represents what happens when the index was built with tokenizer settings other than the
+/// current ones.
+///
+/// # Variants
+///
+/// * `Reindex` - The directory is reindexed with the current settings. This is the default.
+/// * `Warn` - The index is searched as it is, with a warning that the results may be inconsistent,
+///   since the query is tokenized differently from the documents.
+/// * `Ask` - The user is asked whether to reindex, and the index is searched as it is otherwise.
+/// * `Error` - The search is refused with an error naming the conflicting options.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SettingsChange {
+    #[default]
+    Reindex,
+    Warn,
+    Ask,
+    Error,
+}
+
+impl FromStr for SettingsChange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reindex" => Ok(SettingsChange::Reindex),
+            "warn" => Ok(SettingsChange::Warn),
+            "ask" => Ok(SettingsChange::Ask),
+            "error" => Ok(SettingsChange::Error),
+            _ => Err(format!("Unknown settings change handling: {}", s)),
+        }
+    }
+}
+
+/// Represents a part of a result line `Template`.
+///
+/// # Variants
+///
+/// * `Text` - Literal text, written as it is.
+/// * `Rank` - The `{rank}` placeholder, the rank of the result starting at 1.
+/// * `Path` - The `{path}` placeholder, the path of the document.
+/// * `Score` - The `{score}` placeholder, the score of the document.
+/// * `ScorePct` - The `{score_pct}` placeholder, the score as a percentage of the best score.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TemplatePart {
+    Text(String),
+    Rank,
+    Path,
+    Score,
+    ScorePct,
+}
+
+/// Represents the format of a result line, written with `--template`.
+///
+/// The placeholders `{rank}`, `{path}`, `{score}` and `{score_pct}` are replaced by the values
+/// of every result, and `{{` and `}}` are written as literal braces. Unknown placeholders are
+/// rejected when the template is parsed, rather than written out for every result.
+///
+/// # Examples
+///
+/// ```
+/// let template: Template = "{rank}. {path} ({score_pct}%)".parse().unwrap();
+///
+/// assert_eq!(template.render(1, Path::new("a.pdf"), 0.5, 100.0), "1. a.pdf (100%)");
+/// assert!("{title}".parse::<Template>().is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template(Vec<TemplatePart>);
+
+impl Template {
+    /// Formats the result line of a single result.
+    ///
+    /// # Arguments
+    ///
+    /// * `rank` - The rank of the result.
+    /// * `path` - The path of the document.
+    /// * `score` - The score of the document.
+    /// * `score_pct` - The score as a percentage of the best score, written without decimals.
+    ///
+    /// # Returns
+    ///
+    /// The result line, without a trailing newline.
+    pub fn render(&self, rank: usize, path: &Path, score: f32, score_pct: f32) -> String {
+        self.0
+            .iter()
+            .map(|part| match part {
+                TemplatePart::Text(text) => text.clone(),
+                TemplatePart::Rank => rank.to_string(),
+                TemplatePart::Path => path.display().to_string(),
+                TemplatePart::Score => score.to_string(),
+                TemplatePart::ScorePct => format!("{:.0}", score_pct),
+            })
+            .collect()
+    }
+}
+
+impl FromStr for Template {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts: Vec<TemplatePart> = Vec::new();
+        let mut text = String::new();
+        let mut chars = s.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err(format!("Unclosed placeholder in template: {}", s)),
+                        }
+                    }
+                    let placeholder = match name.as_str() {
+                        "rank" => TemplatePart::Rank,
+                        "path" => TemplatePart::Path,
+                        "score" => TemplatePart::Score,
+                        "score_pct" => TemplatePart::ScorePct,
+                        _ => return Err(format!("Unknown template placeholder: {{{}}}", name)),
+                    };
+                    if !text.is_empty() {
+                        parts.push(TemplatePart::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(placeholder);
+                }
+                '}' => return Err(format!("Unmatched '}}' in template: {}", s)),
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(TemplatePart::Text(text));
+        }
+
+        Ok(Template(parts))
+    }
+}
+
+/// Represents the options passed on the command line.
+///
+/// Positional arguments are the filetype, the directory and the optional query, in that order. When the
+/// first positional argument is a command name, the arguments of that command follow it instead:
+/// `export-matrix <filetype> <directory> <output>`,
+/// `export-similarity <filetype> <directory> <output>`, `similar <filetype> <directory> <path>`,
+/// `keywords <filetype> <directory> <path>`, `cooccur <filetype> <directory> <term>`, `verify <filetype> <directory>`,
+/// `vocab <filetype> <directory>`, `tokenize <file or string>` or `clear [directory]`.
+/// Flags (arguments starting with `--`) may appear anywhere on the command line.
+///
+/// # Fields
+///
+/// * `command` - The command to run.
+/// * `filetype` - The file extension to search for.
+/// * `directory` - The directory to index and search.
+/// * `query` - The search query.
+/// * `min_query_len` - The minimum length, in characters, of the longest term of the tokenized
+///   query. Shorter queries are rejected.
+/// * `files_from` - A manifest listing the files to index, one per line, instead of the files of
+///   the directory. The directory still holds the index, and the filetype is ignored.
+/// * `duplicates` - How a file that is listed more than once for indexing is handled.
+/// * `symlinks` - How symbolic links in the directory are handled.
+/// * `include_hidden` - Whether hidden files of the directory, whose name starts with a `.`, are
+///   indexed too.
+/// * `no_precheck` - Whether to skip checking that the files to index are readable and not empty
+///   before reading them.
+/// * `max_file_size` - The size in bytes above which files are skipped instead of indexed.
+/// * `error_report` - The file to write a JSON report of the files skipped while indexing to.
+/// * `index_path` - Where to read and save the index, if it shouldn't be inside the directory.
+/// * `output` - The file to write the search results to instead of the console.
+/// * `store_tokens` - Whether to keep the ordered token list of every document in the index.
+/// * `store_pages` - Whether to keep the pages every term occurs on in the index.
+/// * `store_raw_words` - Whether to keep the distinct words of every document as written, before
+///   normalization, in the index.
+/// * `count` - Whether to only print the number of matching documents instead of the results.
+/// * `snippets` - Whether to print a snippet of context under each result.
+/// * `snippet_strategy` - How the passage shown as snippet is chosen.
+/// * `normalize_scores` - Whether scores are printed as a percentage of the best score.
+/// * `template` - The format of the result lines, replacing the default one.
+/// * `explain` - Whether to print the per-term breakdown of the score under each result.
+/// * `show_zero_scores` - Whether to list the documents that don't match the query too.
+/// * `group_by_dir` - Whether to group the results by their parent directory.
+/// * `title_boost` - The multiplier applied to the score of matches in a document's title.
+/// * `index_paths` - Whether to index the terms of the file path of every document.
+/// * `path_boost` - The multiplier applied to the frequencies of path terms.
+/// * `proximity_boost` - How much documents where the query terms appear close together are
+///   boosted. Zero disables the boost.
+/// * `exact_boost` - How much documents containing the query words exactly as typed are boosted.
+///   Zero disables the boost.
+/// * `recency_boost` - The half-life in days of the decay applied to the score of older
+///   documents. Zero disables the boost.
+/// * `repeated_terms` - How a term that occurs more than once in the query is scored.
+/// * `operator` - How the terms of a multi-term query are combined.
+/// * `order` - The order of the results.
+/// * `tiebreak` - How results with the same score are ordered.
+/// * `collapse_near_dups` - The similarity from which results are collapsed as near-duplicates
+///   of a better ranked result, if set.
+/// * `dedupe_results` - Whether results whose paths resolve to the same file are shown once.
+/// * `since` - The modification time before which documents are left out of the results, if
+///   set.
+/// * `offset` - The number of best ranked results skipped before the results are written.
+/// * `limit` - The largest number of results written after the `offset`, if set.
+/// * `auto_stopwords` - The largest fraction of the documents a query term may occur in before
+///   it is dropped from the query.
+/// * `query_norm` - How the summed score of a multi-term query is normalized.
+/// * `scorer` - The formula documents are ranked with.
+/// * `compare_scorers` - Whether to print the score of every result under each of the built-in
+///   ranking formulas.
+/// * `substring` - Whether query terms starting with `*` match any term containing them.
+/// * `idf` - The formula used to calculate the inverse document frequency.
+/// * `tokenizer` - The `Tokenizer` used for both the documents and the query.
+/// * `builtin_stopwords` - Whether the built-in English stop words are filtered out.
+/// * `boosts_file` - A file of multipliers applied to the score of individual documents.
+/// * `boosts` - The multipliers read from the boosts file, by document path.
+/// * `stopwords_file` - A file of newline-separated stop words to filter out, combined with the
+///   built-in list if that is enabled too.
+/// * `tf_cap` - How much repeating a term in a document counts.
+/// * `read_options` - The options controlling how the text of documents is read.
+/// * `checkpoint_every` - After how many indexed documents the partial index is saved while
+///   indexing. Zero disables checkpoints.
+/// * `sync_checkpoints` - Whether checkpoints are saved by the indexing thread itself instead of
+///   a background thread.
+/// * `dry_run` - Whether to only list the files that would be indexed, without indexing them.
+/// * `no_reindex` - Whether to use the existing index as it is, without ever reindexing it, and
+///   refuse to search an index built with incompatible settings.
+/// * `on_settings_change` - What happens when the index was built with other tokenizer settings
+///   than the current ones. `--no-reindex` always refuses such an index.
+/// * `verbosity` - How much is logged besides the results.
+/// * `yes` - Whether `clear` deletes the index without asking for confirmation.
+/// * `similar_terms` - The number of highest weighted terms of the document `similar` compares.
+/// * `keyword_count` - The number of terms `keywords` and `cooccur` list.
+/// * `sparse` - Whether `export-matrix` writes the sparse triplet format.
+/// * `tfidf` - Whether `export-matrix` writes tf-idf values instead of term frequencies.
+/// * `csv` - Whether `vocab` writes CSV instead of plain text.
+/// * `neighbors` - The number of most similar documents `export-similarity` writes for every
+///   document, instead of the dense matrix, if set.
+#[derive(Debug)]
+pub struct Options {
+    pub command: Command,
+    pub filetype: String,
+    pub directory: PathBuf,
+    pub query: String,
+    pub min_query_len: usize,
+    pub files_from: Option<PathBuf>,
+    pub duplicates: Duplicates,
+    pub symlinks: Symlinks,
+    pub include_hidden: bool,
+    pub no_precheck: bool,
+    pub max_file_size: Option<u64>,
+    pub error_report: Option<PathBuf>,
+    pub index_path: Option<PathBuf>,
+    pub output: Option<PathBuf>,
+    pub store_tokens: bool,
+    pub store_raw_words: bool,
+    pub store_pages: bool,
+    pub count: bool,
+    pub snippets: bool,
+    pub snippet_strategy: SnippetStrategy,
+    pub normalize_scores: bool,
+    pub template: Option<Template>,
+    pub explain: bool,
+    pub show_zero_scores: bool,
+    pub group_by_dir: bool,
+    pub title_boost: f32,
+    pub index_paths: bool,
+    pub path_boost: f32,
+    pub proximity_boost: f32,
+    pub exact_boost: f32,
+    pub recency_boost: f32,
+    pub repeated_terms: RepeatedTerms,
+    pub operator: Operator,
+    pub order: Order,
+    pub tiebreak: Tiebreak,
+    pub collapse_near_dups: Option<f32>,
+    pub dedupe_results: bool,
+    pub since: Option<SystemTime>,
+    pub offset: usize,
+    pub limit: Option<usize>,
+    pub auto_stopwords: f32,
+    pub query_norm: QueryNorm,
+    pub scorer: Ranking,
+    pub compare_scorers: bool,
+    pub substring: bool,
+    pub idf: IdfVariant,
+    pub tokenizer: Tokenizer,
+    pub builtin_stopwords: bool,
+    pub boosts_file: Option<PathBuf>,
+    pub boosts: HashMap<PathBuf, f32>,
+    pub stopwords_file: Option<PathBuf>,
+    pub tf_cap: TfCap,
+    pub read_options: ReadOptions,
+    pub checkpoint_every: usize,
+    pub sync_checkpoints: bool,
+    pub dry_run: bool,
+    pub no_reindex: bool,
+    pub on_settings_change: SettingsChange,
+    pub verbosity: Verbosity,
+    pub yes: bool,
+    pub similar_terms: usize,
+    pub keyword_count: usize,
+    pub sparse: bool,
+    pub tfidf: bool,
+    pub csv: bool,
+    pub neighbors: Option<usize>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            command: Command::default(),
+            filetype: String::new(),
+            directory: PathBuf::new(),
+            query: String::new(),
+            min_query_len: DEFAULT_MIN_QUERY_LEN,
+            files_from: None,
+            duplicates: Duplicates::default(),
+            symlinks: Symlinks::default(),
+            include_hidden: false,
+            no_precheck: false,
+            max_file_size: None,
+            error_report: None,
+            index_path: None,
+            output: None,
+            store_tokens: false,
+            store_raw_words: false,
+            store_pages: false,
+            count: false,
+            snippets: false,
+            snippet_strategy: SnippetStrategy::default(),
+            normalize_scores: false,
+            template: None,
+            explain: false,
+            show_zero_scores: false,
+            group_by_dir: false,
+            title_boost: DEFAULT_TITLE_BOOST,
+            index_paths: false,
+            path_boost: DEFAULT_PATH_BOOST,
+            proximity_boost: 0.0,
+            exact_boost: 0.0,
+            recency_boost: 0.0,
+            repeated_terms: RepeatedTerms::default(),
+            operator: Operator::default(),
+            order: Order::default(),
+            tiebreak: Tiebreak::default(),
+            collapse_near_dups: None,
+            dedupe_results: false,
+            since: None,
+            offset: 0,
+            limit: None,
+            auto_stopwords: 1.0,
+            query_norm: QueryNorm::default(),
+            scorer: Ranking::default(),
+            compare_scorers: false,
+            substring: false,
+            idf: IdfVariant::default(),
+            tokenizer: Tokenizer::default(),
+            builtin_stopwords: false,
+            boosts_file: None,
+            boosts: HashMap::new(),
+            stopwords_file: None,
+            tf_cap: TfCap::default(),
+            read_options: ReadOptions::default(),
+            checkpoint_every: 0,
+            sync_checkpoints: false,
+            dry_run: false,
+            no_reindex: false,
+            on_settings_change: SettingsChange::default(),
+            verbosity: Verbosity::default(),
+            yes: false,
+            similar_terms: DEFAULT_SIMILAR_TERMS,
+            keyword_count: DEFAULT_KEYWORD_COUNT,
+            sparse: false,
+            tfidf: false,
+            csv: false,
+            neighbors: None,
+        }
+    }
+}
+
+impl Options {
+    /// Parses the command line arguments (without the program name) into `Options`.
+    ///
+    /// # Errors
+    ///
+    /// This function returns an error message if an unknown flag is passed, a flag value is
+    /// missing or invalid, a positional argument is missing, or the stop words file can't be read.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let args = vec!["pdf", "books/", "rust", "--snippets"];
+    /// let options = Options::parse(args.into_iter().map(String::from)).unwrap();
+    ///
+    /// assert_eq!(options.query, "rust");
+    /// assert!(options.snippets);
+    /// ```
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+        let mut options = Options::default();
+        let mut positional: Vec<String> = Vec::new();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--files-from" => options.files_from = Some(value(&arg, &mut args)?),
+                "--duplicates" => options.duplicates = value(&arg, &mut args)?,
+                "--symlinks" => options.symlinks = value(&arg, &mut args)?,
+                "--follow-symlinks" => options.symlinks = Symlinks::Follow,
+                "--include-hidden" => options.include_hidden = true,
+                "--no-precheck" => options.no_precheck = true,
+                "--max-file-size" => options.max_file_size = Some(value(&arg, &mut args)?),
+                "--error-report" => options.error_report = Some(value(&arg, &mut args)?),
+                "--index-path" => options.index_path = Some(value(&arg, &mut args)?),
+                "--min-query-len" => options.min_query_len = value(&arg, &mut args)?,
+                "--output" => options.output = Some(value(&arg, &mut args)?),
+                "--store-tokens" => options.store_tokens = true,
+                "--store-raw-words" => options.store_raw_words = true,
+                "--store-pages" => options.store_pages = true,
+                "--count" => options.count = true,
+                "--snippets" => options.snippets = true,
+                "--snippet-strategy" => options.snippet_strategy = value(&arg, &mut args)?,
+                "--normalize-scores" => options.normalize_scores = true,
+                "--template" => options.template = Some(value(&arg, &mut args)?),
+                "--explain" => options.explain = true,
+                "--show-zero-scores" => options.show_zero_scores = true,
+                "--group-by-dir" => options.group_by_dir = true,
+                "--title-boost" => options.title_boost = value(&arg, &mut args)?,
+                "--index-paths" => options.index_paths = true,
+                "--path-boost" => options.path_boost = value(&arg, &mut args)?,
+                "--proximity-boost" => options.proximity_boost = value(&arg, &mut args)?,
+                "--exact-boost" => options.exact_boost = value(&arg, &mut args)?,
+                "--recency-boost" => options.recency_boost = value(&arg, &mut args)?,
+                "--repeated-terms" => options.repeated_terms = value(&arg, &mut args)?,
+                "--default-operator" => options.operator = value(&arg, &mut args)?,
+                "--order" => options.order = value(&arg, &mut args)?,
+                "--tiebreak" => options.tiebreak = value(&arg, &mut args)?,
+                "--dedupe-results" => options.dedupe_results = true,
+                "--collapse-near-dups" => {
+                    options.collapse_near_dups = Some(value(&arg, &mut args)?)
+                }
+                "--since" => options.since = Some(parse_since(&value::<String>(&arg, &mut args)?)?),
+                "--offset" => options.offset = value(&arg, &mut args)?,
+                "--limit" => options.limit = Some(value(&arg, &mut args)?),
+                "--auto-stopwords" => options.auto_stopwords = value(&arg, &mut args)?,
+                "--normalize" => options.query_norm = value(&arg, &mut args)?,
+                "--scorer" => options.scorer = value(&arg, &mut args)?,
+                "--compare-scorers" => options.compare_scorers = true,
+                "--substring" => options.substring = true,
+                "--idf" => options.idf = value(&arg, &mut args)?,
+                "--fold-accents" => options.tokenizer.fold_accents = true,
+                "--builtin-stopwords" => options.builtin_stopwords = true,
+                "--boosts" => options.boosts_file = Some(value(&arg, &mut args)?),
+                "--stopwords" => options.stopwords_file = Some(value(&arg, &mut args)?),
+                "--language" => options.tokenizer.language = value(&arg, &mut args)?,
+                "--links" => options.tokenizer.links = value(&arg, &mut args)?,
+                "--abbreviations" => options.tokenizer.abbreviations = true,
+                "--keep-decimals" => options.tokenizer.decimals = true,
+                "--max-token-len" => {
+                    options.tokenizer.max_token_len = Some(value(&arg, &mut args)?)
+                }
+                "--pipeline" => options.tokenizer.pipeline = value(&arg, &mut args)?,
+                "--apostrophes" => options.tokenizer.apostrophes = value(&arg, &mut args)?,
+                "--tf-cap" => options.tf_cap = value(&arg, &mut args)?,
+                "--dehyphenate" => options.read_options.dehyphenate = true,
+                "--strip-invalid" => options.read_options.strip_invalid = true,
+                "--ligatures" => options.read_options.ligatures = value(&arg, &mut args)?,
+                "--pdf-password" => options.read_options.password = value(&arg, &mut args)?,
+                "--checkpoint-every" => options.checkpoint_every = value(&arg, &mut args)?,
+                "--sync-checkpoints" => options.sync_checkpoints = true,
+                "--dry-run" => options.dry_run = true,
+                "--no-reindex" => options.no_reindex = true,
+                "--on-settings-change" => options.on_settings_change = value(&arg, &mut args)?,
+                "-q" | "--quiet" => options.verbosity = Verbosity::Quiet,
+                "-v" | "--verbose" => options.verbosity = Verbosity::Verbose,
+                "--yes" => options.yes = true,
+                "--similar-terms" => options.similar_terms = value(&arg, &mut args)?,
+                "--keyword-count" => options.keyword_count = value(&arg, &mut args)?,
+                "--sparse" => options.sparse = true,
+                "--tfidf" => options.tfidf = true,
+                "--csv" => options.csv = true,
+                "--neighbors" => options.neighbors = Some(value(&arg, &mut args)?),
+                flag if flag.starts_with("--") => return Err(format!("Unknown option {}", flag)),
+                _ => positional.push(arg),
+            }
+        }
+
+        // A NaN or infinite boost would turn every score it touches into NaN
+        for (flag, boost) in [
+            ("--title-boost", options.title_boost),
+            ("--path-boost", options.path_boost),
+            ("--proximity-boost", options.proximity_boost),
+            ("--exact-boost", options.exact_boost),
+            ("--recency-boost", options.recency_boost),
+        ] {
+            if !boost.is_finite() {
+                return Err(format!("{} must be a finite number, got {}", flag, boost));
+            }
+        }
+        if !(0.0..=1.0).contains(&options.auto_stopwords) {
+            return Err(format!(
+                "--auto-stopwords must be between 0 and 1, got {}",
+                options.auto_stopwords
+            ));
+        }
+        if let Some(threshold) = options.collapse_near_dups {
+            if !(0.0..=1.0).contains(&threshold) {
+                return Err(format!(
+                    "--collapse-near-dups must be between 0 and 1, got {}",
+                    threshold
+                ));
+            }
+        }
+        if options.tokenizer.max_token_len == Some(0) {
+            return Err("--max-token-len must be at least 1".to_string());
+        }
+        options.tokenizer.stopwords = options.stopwords()?;
+        if let Some(path) = &options.boosts_file {
+            options.boosts = read_boosts(path)
+                .map_err(|e| format!("Can't read boosts from {:?}: {}", path, e))?;
+        }
+
+        let mut positional = positional.into_iter().peekable();
+        let command = match positional.peek().map(String::as_str) {
+            Some("export-matrix")
+            | Some("export-similarity")
+            | Some("tokenize")
+            | Some("clear")
+            | Some("similar")
+            | Some("keywords")
+            | Some("cooccur")
+            | Some("verify")
+            | Some("vocab") => positional.next(),
+            _ => None,
+        };
+
+        if command.as_deref() == Some("tokenize") {
+            let input = positional.next().ok_or("Enter a file or a string")?;
+            options.command = Command::Tokenize { input };
+            return Ok(options);
+        }
+
+        if command.as_deref() == Some("clear") {
+            match positional.next() {
+                Some(directory) => options.directory = PathBuf::from(directory),
+                None if options.index_path.is_some() => {}
+                None => return Err("Enter a directory or --index-path".to_string()),
+            }
+            options.command = Command::Clear;
+            return Ok(options);
+        }
+
+        if let Some(name @ ("verify" | "vocab")) = command.as_deref() {
+            options.filetype = positional.next().ok_or("Enter a filetype")?;
+            options.directory = PathBuf::from(positional.next().ok_or("Enter a directory")?);
+            options.command = match name {
+                "verify" => Command::Verify,
+                _ => Command::Vocab,
+            };
+            return Ok(options);
+        }
+
+        if positional.len() < 2 {
+            return Err("Enter filetype, directory, word".to_string());
+        }
+
+        options.filetype = positional.next().ok_or("Enter a filetype")?;
+        options.directory = PathBuf::from(positional.next().ok_or("Enter a directory")?);
+
+        match command.as_deref() {
+            Some("export-matrix") => {
+                let output = positional.next().ok_or("Enter an output file")?;
+                options.command = Command::ExportMatrix {
+                    output: PathBuf::from(output),
+                };
+            }
+            Some("export-similarity") => {
+                let output = positional.next().ok_or("Enter an output file")?;
+                options.command = Command::ExportSimilarity {
+                    output: PathBuf::from(output),
+                };
+            }
+            Some("similar") => {
+                let path = positional.next().ok_or("Enter the path of a document")?;
+                options.command = Command::Similar {
+                    path: PathBuf::from(path),
+                };
+            }
+            Some("keywords") => {
+                let path = positional.next().ok_or("Enter the path of a document")?;
+                options.command = Command::Keywords {
+                    path: PathBuf::from(path),
+                };
+            }
+            Some("cooccur") => {
+                let term = positional.next().ok_or("Enter a term")?;
+                options.command = Command::Cooccur { term };
+            }
+            _ => match positional.next() {
+                Some(query) => options.query = query,
+                None => options.command = Command::Index,
+            },
+        }
+
+        Ok(options)
+    }
+
+    /// Parses the command line arguments like `parse`, on top of the defaults of a config file.
+    ///
+    /// The config file is the `--config <file>` argument if given, otherwise the file at
+    /// `default_config_path` if it exists. `--no-config` skips the config file. The options of
+    /// the config file are parsed before the command line arguments, so flags given on the
+    /// command line override them. See `config_args` for the format.
+    ///
+    /// # Errors
+    ///
+    /// This function returns an error message if the config file can't be read or is invalid,
+    /// or for the errors of `parse`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// // With `index-path = "/tmp/books.json"` in ~/.config/tf-idf/config.toml
+    /// let args = vec!["pdf", "books/", "rust"];
+    /// let options = Options::parse_with_config(args.into_iter().map(String::from)).unwrap();
+    ///
+    /// assert_eq!(options.index_path, Some(PathBuf::from("/tmp/books.json")));
+    /// ```
+    pub fn parse_with_config(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+        let mut config: Option<PathBuf> = None;
+        let mut no_config = false;
+        let mut rest: Vec<String> = Vec::new();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--config" => config = Some(value(&arg, &mut args)?),
+                "--no-config" => no_config = true,
+                _ => rest.push(arg),
+            }
+        }
+
+        let config = match config {
+            Some(path) => Some(path),
+            None if no_config => None,
+            None => default_config_path().filter(|path| path.is_file()),
+        };
+        let mut all_args = match config {
+            Some(path) => config_args(&path)?,
+            None => Vec::new(),
+        };
+        all_args.extend(rest);
+
+        Options::parse(all_args.into_iter())
+    }
+
+    /// Builds the set of stop words from the built-in list and the stop words file.
+    ///
+    /// # Errors
+    ///
+    /// This function returns an error message if the stop words file can't be read.
+    fn stopwords(&self) -> Result<Stopwords, String> {
+        let mut words: Vec<String> = Vec::new();
+
+        if self.builtin_stopwords {
+            words.extend(ENGLISH_STOPWORDS.iter().map(|word| word.to_string()));
+        }
+
+        if let Some(path) = &self.stopwords_file {
+            let content = read_to_string(path)
+                .map_err(|e| format!("Can't read stop words from {:?}: {}", path, e))?;
+            words.extend(content.lines().map(String::from));
+        }
+
+        Ok(Stopwords::new(words))
+    }
+
+    /// Returns the `IndexSettings` of the indexing flags.
+    pub fn index_settings(&self) -> IndexSettings {
+        IndexSettings {
+            version: INDEX_VERSION,
+            tokenizer: self.tokenizer.clone(),
+            tf_cap: self.tf_cap,
+        }
+    }
+
+    /// Returns the `SearchOpts` of the search flags.
+    pub fn search_opts(&self) -> SearchOpts<'_> {
+        SearchOpts {
+            title_boost: self.title_boost,
+            proximity_boost: self.proximity_boost,
+            exact_boost: self.exact_boost,
+            recency_boost: self.recency_boost,
+            query_norm: self.query_norm,
+            idf: self.idf,
+            show_zero_scores: self.show_zero_scores,
+            repeated_terms: self.repeated_terms,
+            operator: self.operator,
+            order: self.order,
+            tiebreak: self.tiebreak,
+            auto_stopwords: self.auto_stopwords,
+            since: self.since,
+            compare_scorers: self.compare_scorers,
+            substring: self.substring,
+            boosts: Some(&self.boosts).filter(|boosts| !boosts.is_empty()),
+        }
+    }
+
+    /// Returns the path of the index file.
+    ///
+    /// This is the `--index-path` argument if given, otherwise `.data.json` inside the directory.
+    pub fn index_path(&self) -> PathBuf {
+        match &self.index_path {
+            Some(path) => path.clone(),
+            None => self.directory.join(INDEX_FILE_NAME),
+        }
+    }
+}
+
+/// Returns the standard location of the config file.
+///
+/// This is `tf-idf/config.toml` inside `$XDG_CONFIG_HOME`, or else inside `~/.config`. On systems
+/// without a home directory, such as Windows, `%APPDATA%` is used instead.
+pub fn default_config_path() -> Option<PathBuf> {
+    let dir = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .or_else(|| env::var_os("APPDATA").map(PathBuf::from))?;
+
+    Some(dir.join("tf-idf").join(CONFIG_FILE_NAME))
+}
+
+/// Reads a TOML config file into the command line arguments it stands for.
+///
+/// Every key is the name of a flag without its leading dashes, such as `index-path`,
+/// `pdf-password` or `fold-accents`. Flags without a value are enabled with `true` and left out
+/// with `false`, while the values of the other flags are strings or numbers. The config only
+/// sets flags, not the filetype, directory or query.
+///
+/// ```toml
+/// index-path = "/home/me/.cache/books.json"
+/// pdf-password = "secret"
+/// fold-accents = true
+/// title-boost = 3.0
+/// ```
+///
+/// # Errors
+///
+/// This function returns an error message if the file can't be read, isn't valid TOML, or holds
+/// a value that isn't a string, number or boolean.
+fn config_args(path: &Path) -> Result<Vec<String>, String> {
+    let content =
+        read_to_string(path).map_err(|e| format!("Can't read config file {:?}: {}", path, e))?;
+    let table: toml::Table = content
+        .parse()
+        .map_err(|e| format!("Invalid config file {:?}: {}", path, e))?;
+    let mut args: Vec<String> = Vec::new();
+
+    for (key, value) in table {
+        let flag = format!("--{}", key);
+        match value {
+            toml::Value::Boolean(true) => args.push(flag),
+            toml::Value::Boolean(false) => {}
+            toml::Value::String(value) => args.extend([flag, value]),
+            toml::Value::Integer(value) => args.extend([flag, value.to_string()]),
+            toml::Value::Float(value) => args.extend([flag, value.to_string()]),
+            _ => {
+                return Err(format!(
+                "Invalid value for {} in config file {:?}, expected a string, number or boolean",
+                key, path
+            ))
+            }
+        }
+    }
+
+    Ok(args)
+}
+
+/// Parses the value of `--since` into the earliest modification time to include.
+///
+/// The value is either an age relative to now, a number followed by `h` for hours, `d` for days
+/// or `w` for weeks, or a date in the form `YYYY-MM-DD`, meaning midnight UTC at the start of
+/// that day.
+///
+/// # Arguments
+///
+/// * `since` - The value of the flag, such as `7d` or `2024-01-01`.
+///
+/// # Returns
+///
+/// The `SystemTime` documents have to be modified at or after.
+///
+/// # Errors
+///
+/// This function returns an error message if the value is neither an age nor a valid date.
+fn parse_since(since: &str) -> Result<SystemTime, String> {
+    let invalid = || {
+        format!(
+            "Invalid value for --since: {}, expected an age such as 7d or a date such as 2024-01-01",
+            since
+        )
+    };
+
+    if let Some(unit) = since
+        .chars()
+        .last()
+        .filter(|unit| unit.is_ascii_alphabetic())
+    {
+        let seconds = match unit {
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            'w' => 7 * 24 * 60 * 60,
+            _ => return Err(invalid()),
+        };
+        let amount: u64 = since[..since.len() - 1].parse().map_err(|_| invalid())?;
+        let age = Duration::from_secs(amount.saturating_mul(seconds));
+        return Ok(SystemTime::now().checked_sub(age).unwrap_or(UNIX_EPOCH));
+    }
+
+    let parts: Vec<&str> = since.split('-').collect();
+    let [year, month, day] = parts[..] else {
+        return Err(invalid());
+    };
+    let year: i64 = year.parse().map_err(|_| invalid())?;
+    let month: i64 = month.parse().map_err(|_| invalid())?;
+    let day: i64 = day.parse().map_err(|_| invalid())?;
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let month_days = [
+        31,
+        if leap { 29 } else { 28 },
+        31,
+        30,
+        31,
+        30,
+        31,
+        31,
+        30,
+        31,
+        30,
+        31,
+    ];
+    if !(1..=12).contains(&month) || !(1..=month_days[month as usize - 1]).contains(&day) {
+        return Err(invalid());
+    }
+
+    // Days since 1970-01-01 of the proleptic Gregorian calendar, counting years from March so
+    // the leap day is the last day of the year
+    let (year, month) = if month <= 2 {
+        (year.checked_sub(1).ok_or_else(invalid)?, month + 9)
+    } else {
+        (year, month - 3)
+    };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let day_of_year = (153 * month + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    // Dates far enough in the past or future overflow, and aren't representable anyway
+    let seconds = era
+        .checked_mul(146_097)
+        .and_then(|days| days.checked_add(day_of_era - 719_468))
+        .and_then(|days| days.checked_mul(24 * 60 * 60))
+        .ok_or_else(invalid)?;
+    let offset = Duration::from_secs(seconds.unsigned_abs());
+    if seconds >= 0 {
+        UNIX_EPOCH.checked_add(offset)
+    } else {
+        UNIX_EPOCH.checked_sub(offset)
+    }
+    .ok_or_else(invalid)
+}
+
+/// Takes the value of `flag` from the remaining arguments and parses it.
+///
+/// # Errors
+///
+/// This function returns an error message if the value is missing or can't be parsed.
+fn value<T: FromStr>(flag: &str, args: &mut impl Iterator<Item = String>) -> Result<T, String> {
+    let raw = args
+        .next()
+        .ok_or_else(|| format!("Missing value for {}", flag))?;
+
+    raw.parse()
+        .map_err(|_| format!("Invalid value for {}: {}", flag, raw))
+}
+
+#[cfg(all(test, windows))]
+mod tests {
+    use super::Options;
+    use std::path::Path;
+
+    fn parse(args: &[&str]) -> Options {
+        Options::parse(args.iter().map(|arg| arg.to_string())).expect("Invalid arguments")
+    }
+
+    #[test]
+    fn index_path_accepts_backslash_separated_directories() {
+        let options = parse(&["pdf", r"data\books", "rust"]);
+        assert_eq!(options.index_path(), Path::new(r"data\books\.data.json"));
+        assert_eq!(options.index_path(), Path::new("data/books/.data.json"));
+    }
+
+    #[test]
+    fn index_path_accepts_backslash_separated_index_paths() {
+        let options = parse(&[
+            "--index-path",
+            r"C:\cache\books.json",
+            "pdf",
+            "data/books",
+            "rust",
+        ]);
+        assert_eq!(options.index_path(), Path::new("C:/cache/books.json"));
+        assert_eq!(options.index_path().parent(), Some(Path::new(r"C:\cache")));
+    }
+}